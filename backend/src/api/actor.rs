@@ -0,0 +1,39 @@
+// actor.rs
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use std::{convert::Infallible, net::SocketAddr};
+
+/// Who sent a request, recorded in the audit log.
+/// Taken from the optional `X-User` header and the client address.
+pub struct Actor(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user = parts
+            .headers
+            .get("x-user")
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+
+        let client = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+
+        let actor = match (user, client) {
+            (Some(user), Some(client)) => format!("{} ({})", user, client),
+            (Some(user), None) => user.to_string(),
+            (None, Some(client)) => client,
+            (None, None) => "unknown".to_string(),
+        };
+
+        Ok(Actor(actor))
+    }
+}
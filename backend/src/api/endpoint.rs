@@ -1,33 +1,54 @@
 // endpoint.rs
 use axum::{
-    extract::{Extension, Json, Multipart, Path},
-    http::StatusCode,
-    response::{Json as AxumJson, Response},
+    body::Body,
+    extract::{Extension, Json, Multipart, Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json as AxumJson, Response},
 };
 
-use csv::ReaderBuilder;
-use std::{fmt::Debug, io::Cursor};
+use csv::WriterBuilder;
+use futures::stream::{self, StreamExt};
+use rusqlite::{Connection, TransactionBehavior};
+use rust_xlsxwriter::Workbook;
+use tokio::sync::mpsc;
+use tokio_util::io::ReaderStream;
+use std::{collections::HashMap, fmt::Debug};
 
+use crate::api::actor::Actor;
 use crate::core::{
+    audit::fetch_audit_log,
+    backup::{
+        backup_to_temp_file, check_backup, check_dump, dump, restore_backup, restore_dump,
+        BackupKind, BackupStore, CheckedBackup, Dump, TempFile,
+    },
+    error::{AppError, AppResult},
+    import::{
+        fetch_import_batches, import_records, read_csv, revert_import_batch, row_fields, CsvDialect,
+        ImportMode, ImportOptions, ImportRow,
+    },
+    jobs::JobQueue,
+    xlsx::{add_sheet, read_xlsx, xlsx_error},
+    query::ListQuery,
     statistics::stats::get_statistics,
-    traits::Searchable,
-    types::{DbPool, Order, Statistics},
+    traits::{CsvRecord, Searchable, TextSearchable},
+    types::{Article, AuditEntry, Customer, DbPool, ImportBatch, Job, Order, OrderStatus, Page, Statistics, StatusChange},
 };
 
 use crate::core::{
     operations::{
-        delete_record_by_id, establish_connection, fetch_all_records, insert_record, update_record,
+        change_order_status, delete_record_by_id, establish_connection, fetch_records,
+        fetch_status_history, find_record_by_id, in_transaction, insert_record, patch_record,
+        restore_record, search_records, update_record, DeletePolicy, DeletionTokenStore,
     },
     traits::{Insertable, Mappable},
 };
 use serde_json::json;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::core::pdf::generation::fetch_pdf;
 
 
-
-
 #[utoipa::path(
     get,
     path = "/search/{id}",
@@ -36,6 +57,7 @@ use crate::core::pdf::generation::fetch_pdf;
     ),
     responses(
         (status = 200, description = "Item found", body = ApiResponse),
+        (status = 404, description = "No item with this ID", body = serde_json::Value),
         (status = 500, description = "Error occured while searching", body = serde_json::Value)
     )
 )]
@@ -43,40 +65,66 @@ use crate::core::pdf::generation::fetch_pdf;
 pub async fn handle_search<T: Mappable + Insertable + Searchable>(
     Extension(pool): Extension<DbPool>,
     id: Path<i32>,
-) -> Result<AxumJson<T>, (StatusCode, AxumJson<serde_json::Value>)> {
+) -> AppResult<AxumJson<T>> {
     let conn = establish_connection(&pool)?;
     let search_id = id.0;
 
-    match T::search(&conn, search_id) {
-        Ok(item) => Ok(AxumJson(item)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            AxumJson(json!({"error": format!("Failed to search id {}: {}", search_id, e)})),
-        )),
-    }
+    Ok(AxumJson(T::search(&conn, search_id)?))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct TextSearchParams {
+    /// Search text, every word has to match
+    q: String,
+    /// Maximum number of results, 20 if omitted
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(TextSearchParams),
+    responses(
+        (status = 200, description = "Matching records, best match first", body = [ApiResponse]),
+        (status = 500, description = "Error occured while searching", body = serde_json::Value)
+    )
+)]
+// GET <T>/search?q=
+pub async fn handle_text_search<T: Mappable + TextSearchable + Serialize>(
+    Extension(pool): Extension<DbPool>,
+    Query(params): Query<TextSearchParams>,
+) -> AppResult<AxumJson<Vec<T>>> {
+    let conn = establish_connection(&pool)?;
+
+    Ok(AxumJson(search_records::<T>(&conn, &params.q, params.limit.unwrap_or(20))?))
 }
 
 #[utoipa::path(
     get,
     path = "/records",
+    params(
+        ("limit" = Option<i64>, Query, description = "Maximum number of records, all if omitted"),
+        ("offset" = Option<i64>, Query, description = "Number of records to skip"),
+        ("sort" = Option<String>, Query, description = "Sort order as field:asc or field:desc"),
+        ("include_deleted" = Option<bool>, Query, description = "Include soft deleted records"),
+        ("filters" = Option<HashMap<String, String>>, Query, style = Form, explode,
+            description = "Column filters as field=value, field_min=value or field_max=value")
+    ),
     responses(
-        (status = 200, description = "Returned all records", body = [ApiResponse]),
-        (status = 500, description = "Error while returning all records", body = serde_json::Value)
+        (status = 200, description = "Returned a page of records with the total count", body = serde_json::Value),
+        (status = 422, description = "Unknown field or invalid parameter", body = serde_json::Value),
+        (status = 500, description = "Error while returning records", body = serde_json::Value)
     )
 )]
-// GET /<T>
-pub async fn handle_fetch_records<T: Mappable + Insertable + Debug>(
+// GET /<T>?limit=&offset=&sort=&<field>=
+pub async fn handle_fetch_records<T: Mappable + Insertable + Debug + Serialize>(
     Extension(pool): Extension<DbPool>,
-) -> Result<AxumJson<Vec<T>>, (StatusCode, AxumJson<serde_json::Value>)> {
+    Query(params): Query<HashMap<String, String>>,
+) -> AppResult<AxumJson<Page<T>>> {
     let conn = establish_connection(&pool)?;
+    let query = ListQuery::parse::<T>(&params)?;
 
-    match fetch_all_records::<T>(&conn) {
-        Ok(item_list) => Ok(AxumJson(item_list)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            AxumJson(json!({ "error": format!("Failed to get connection: {}", e) })),
-        )),
-    }
+    Ok(AxumJson(fetch_records::<T>(&conn, &query)?))
 }
 
 
@@ -85,50 +133,117 @@ pub async fn handle_fetch_records<T: Mappable + Insertable + Debug>(
     path = "/add",
     request_body = serde_json::Value,
     responses(
-        (status = 200, description = "Record added", body = serde_json::Value),
-        (status = 400, description = "Error while adding record", body = serde_json::Value)
+        (status = 201, description = "Record added, returned with its assigned ID", body = serde_json::Value),
+        (status = 409, description = "Duplicate ID or insufficient stock", body = serde_json::Value),
+        (status = 422, description = "Invalid record", body = serde_json::Value),
+        (status = 500, description = "Error while adding record", body = serde_json::Value)
     )
 )]
 // POST /<T>/add
-pub async fn handle_create_record<T: Mappable + Insertable + Debug>(
+pub async fn handle_create_record<T: Mappable + Insertable + Debug + Serialize>(
     Extension(pool): Extension<DbPool>,
-    Json(item): Json<T>,
-) -> Result<(StatusCode, AxumJson<serde_json::Value>), (StatusCode, AxumJson<serde_json::Value>)> {
-    let conn = establish_connection(&pool)?;
+    Actor(actor): Actor,
+    Json(mut item): Json<T>,
+) -> AppResult<(StatusCode, AxumJson<serde_json::Value>)> {
+    let mut conn = establish_connection(&pool)?;
 
-    match insert_record::<T>(&conn, &item) {
-        Ok(_) => Ok((
-            StatusCode::OK,
-            AxumJson(json!({ "message": format!("Item with ID #{} added successfully", item.id_value()) })),
-        )),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            AxumJson(json!({"error": format!("Failed to add item: {}", e)})),
-        )),
-    }
+    // Read back the stored record, so server-side values like the ID are included
+    let created = in_transaction(&mut conn, |tx| {
+        let id_value = insert_record::<T>(tx, &mut item, &actor)?;
+        find_record_by_id::<T>(tx, id_value)
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        AxumJson(json!({
+            "message": format!("Item with ID #{} added successfully", created.id_value()),
+            "data": created,
+        })),
+    ))
 }
 
 
+#[derive(Deserialize)]
+pub struct DeleteParams {
+    /// Delete orders referencing the record as well
+    cascade: Option<bool>,
+    /// Token to confirm deleting every record, returned by a request without it
+    confirm: Option<String>,
+}
+
 // DELETE /<T>/delete (optional /:id)
-pub async fn handle_delete_record<T: Mappable + Insertable + Debug>(
+pub async fn handle_delete_record<T: Mappable + Insertable + Debug + Serialize>(
     Extension(pool): Extension<DbPool>,
+    Extension(tokens): Extension<DeletionTokenStore>,
+    Actor(actor): Actor,
     id: Option<Path<i32>>,
-) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
-    let conn = establish_connection(&pool)?;
+    Query(params): Query<DeleteParams>,
+) -> AppResult<(StatusCode, Json<serde_json::Value>)> {
+    let mut conn = establish_connection(&pool)?;
     let deletion_id = id.map(|id| id.0);
+    let policy = if params.cascade.unwrap_or(false) {
+        DeletePolicy::Cascade
+    } else {
+        DeletePolicy::Restrict
+    };
+
+    in_transaction(&mut conn, |tx| {
+        if deletion_id.is_none() {
+            let confirmed = match &params.confirm {
+                Some(token) => tokens.confirm::<T>(tx, token)?,
+                None => false,
+            };
+            if !confirmed {
+                let token = tokens.issue::<T>(tx)?;
+                return Err(AppError::ConfirmationRequired { token });
+            }
+        }
 
-    match delete_record_by_id::<T>(&conn, &deletion_id) {
-        Ok(_) => Ok((
-            StatusCode::OK,
-            Json(
-                json!({ "message": format!("Item {} deleted successfully", deletion_id.unwrap_or(-1)) }),
-            ),
-        )),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            Json(json!({ "error": format!("Failed to delete article: {}", e) })),
-        )),
-    }
+        delete_record_by_id::<T>(tx, &deletion_id, policy, &actor)
+    })?;
+
+    let message = match deletion_id {
+        Some(id_value) => format!("Item {} deleted successfully", id_value),
+        None => "All items deleted successfully".to_string(),
+    };
+
+    Ok((StatusCode::OK, Json(json!({ "message": message }))))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/restore/{id}",
+    params(
+        ("id" = i32, Path, description = "ID of the deleted record")
+    ),
+    responses(
+        (status = 200, description = "Record restored", body = serde_json::Value),
+        (status = 404, description = "No deleted record with this ID", body = serde_json::Value),
+        (status = 409, description = "Insufficient stock to restore an order", body = serde_json::Value)
+    )
+)]
+// POST /<T>/restore/:id
+pub async fn handle_restore_record<T: Mappable + Insertable + Debug + Serialize>(
+    Extension(pool): Extension<DbPool>,
+    Actor(actor): Actor,
+    id: Path<i32>,
+) -> AppResult<(StatusCode, Json<serde_json::Value>)> {
+    let mut conn = establish_connection(&pool)?;
+    let restore_id = id.0;
+
+    let restored = in_transaction(&mut conn, |tx| {
+        restore_record::<T>(tx, restore_id, &actor)?;
+        find_record_by_id::<T>(tx, restore_id)
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "message": format!("Item {} restored successfully", restore_id),
+            "data": restored,
+        })),
+    ))
 }
 
 
@@ -138,30 +253,144 @@ pub async fn handle_delete_record<T: Mappable + Insertable + Debug>(
     request_body = serde_json::Value,
     responses(
         (status = 200, description = "Record updated", body = serde_json::Value),
-        (status = 400, description = "Error while updating", body = serde_json::Value)
+        (status = 404, description = "No record with this ID", body = serde_json::Value),
+        (status = 500, description = "Error while updating", body = serde_json::Value)
     )
 )]
 // PUT /<T>/update
-pub async fn handle_update_record<T: Mappable + Insertable + Debug>(
+pub async fn handle_update_record<T: Mappable + Insertable + Debug + Serialize>(
     Extension(pool): Extension<DbPool>,
+    Actor(actor): Actor,
     Json(updated_item): Json<T>,
-) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
+) -> AppResult<(StatusCode, Json<serde_json::Value>)> {
+    let mut conn = establish_connection(&pool)?;
+
+    in_transaction(&mut conn, |tx| update_record(tx, &updated_item, &actor))?;
+
+    Ok((
+        StatusCode::OK,
+        AxumJson(
+            json!({ "message": format!("Record {} updated successfully", updated_item.id_value()) }),
+        ),
+    ))
+}
+
+
+#[utoipa::path(
+    patch,
+    path = "/{id}",
+    params(
+        ("id" = i32, Path, description = "ID of the record")
+    ),
+    request_body(content = serde_json::Value, description = "JSON merge patch with only the fields to change"),
+    responses(
+        (status = 200, description = "Record patched, returns the updated record", body = serde_json::Value),
+        (status = 404, description = "No record with this ID", body = serde_json::Value),
+        (status = 422, description = "Unknown field or invalid value", body = serde_json::Value)
+    )
+)]
+// PATCH /<T>/:id
+pub async fn handle_patch_record<T: Mappable + Insertable + Serialize + DeserializeOwned>(
+    Extension(pool): Extension<DbPool>,
+    Actor(actor): Actor,
+    id: Path<i32>,
+    Json(patch): Json<serde_json::Value>,
+) -> AppResult<AxumJson<T>> {
+    let mut conn = establish_connection(&pool)?;
+
+    Ok(AxumJson(in_transaction(&mut conn, |tx| {
+        patch_record::<T>(tx, id.0, &patch, &actor)
+    })?))
+}
+
+
+#[derive(Deserialize, ToSchema)]
+pub struct StatusRequest {
+    status: OrderStatus,
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/status",
+    params(
+        ("id" = i32, Path, description = "ID of the order")
+    ),
+    request_body = StatusRequest,
+    responses(
+        (status = 200, description = "Status changed, returns the updated order", body = Order),
+        (status = 404, description = "No order with this ID", body = serde_json::Value),
+        (status = 409, description = "Transition not allowed from the current status, or the order already has the status", body = serde_json::Value)
+    )
+)]
+// POST /orders/:id/status
+pub async fn handle_change_order_status(
+    Extension(pool): Extension<DbPool>,
+    Actor(actor): Actor,
+    id: Path<i32>,
+    Json(request): Json<StatusRequest>,
+) -> AppResult<AxumJson<Order>> {
+    let mut conn = establish_connection(&pool)?;
+
+    Ok(AxumJson(in_transaction(&mut conn, |tx| {
+        change_order_status(tx, id.0, request.status, &actor)
+    })?))
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/status_history",
+    params(
+        ("id" = i32, Path, description = "ID of the order")
+    ),
+    responses(
+        (status = 200, description = "Status changes of the order, oldest first", body = [StatusChange]),
+        (status = 500, description = "Error fetching the history", body = serde_json::Value)
+    )
+)]
+// GET /orders/:id/status_history
+pub async fn handle_status_history(
+    Extension(pool): Extension<DbPool>,
+    id: Path<i32>,
+) -> AppResult<AxumJson<Vec<StatusChange>>> {
     let conn = establish_connection(&pool)?;
 
-    match update_record(&conn, &updated_item) {
-        Ok(_) => Ok((
-            StatusCode::OK,
-            AxumJson(
-                json!({ "message": format!("Record {} updated successfully", updated_item.id_value()) }),
-            ),
-        )),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            AxumJson(
-                json!({ "error": format!("Failed to update record {}: {}", updated_item.id_value(), e) }),
-            ),
-        )),
-    }
+    Ok(AxumJson(fetch_status_history(&conn, id.0)?))
+}
+
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuditParams {
+    /// Entity type: article, customer or order
+    entity: Option<String>,
+    /// ID of the record
+    id: Option<i32>,
+    /// Maximum number of entries, 100 if omitted
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    params(AuditParams),
+    responses(
+        (status = 200, description = "Audit entries, newest first", body = [AuditEntry]),
+        (status = 500, description = "Error fetching the audit log", body = serde_json::Value)
+    )
+)]
+// GET /audit?entity=&id=
+pub async fn handle_audit_log(
+    Extension(pool): Extension<DbPool>,
+    Query(params): Query<AuditParams>,
+) -> AppResult<AxumJson<Vec<AuditEntry>>> {
+    let conn = establish_connection(&pool)?;
+
+    Ok(AxumJson(fetch_audit_log(
+        &conn,
+        params.entity.as_deref(),
+        params.id,
+        params.limit.unwrap_or(100),
+    )?))
 }
 
 
@@ -170,22 +399,16 @@ pub async fn handle_update_record<T: Mappable + Insertable + Debug>(
     path = "/operations/statistics",
     responses(
         (status = 200, description = "Statistics fetched", body = Statistics),
-        (status = 400, description = "Error fetching statistics", body = serde_json::Value)
+        (status = 500, description = "Error fetching statistics", body = serde_json::Value)
     )
 )]
 // GET /operations/statistics
 pub async fn handle_statistics(
     Extension(pool): Extension<DbPool>,
-) -> Result<AxumJson<Statistics>, (StatusCode, AxumJson<serde_json::Value>)> {
+) -> AppResult<AxumJson<Statistics>> {
     let conn = establish_connection(&pool)?;
 
-    match get_statistics(&conn) {
-        Ok(statistics) => Ok(AxumJson(statistics)),
-        Err(e) => Err((
-            StatusCode::BAD_REQUEST,
-            AxumJson(json!({ "error": format!("Failed to retrieve statistics: {}", e) })),
-        )),
-    }
+    Ok(AxumJson(get_statistics(&conn)?))
 }
 
 
@@ -195,72 +418,540 @@ pub async fn handle_statistics(
     request_body = Order,
     responses(
         (status = 200, description = "PDF generated"),
-        (status = 400, description = "Error generating PDF", body = serde_json::Value)
+        (status = 500, description = "Error generating PDF", body = serde_json::Value)
     )
 )]
 // POST /pdf_gen
 pub async fn handle_generate_pdf(
     Json(order): Json<Order>,
-) -> Result<Response, (StatusCode, AxumJson<serde_json::Value>)> {
-    let pdf_response = fetch_pdf(Json(order)).await;
-    Ok(pdf_response)
+) -> AppResult<Response> {
+    fetch_pdf(Json(order)).await
 }
 
 
 
+#[derive(Deserialize, IntoParams)]
+pub struct ImportParams {
+    /// Handling of rows with the ID of an existing record, insert if omitted
+    #[param(inline)]
+    mode: Option<ImportMode>,
+    /// Only validate the rows and report the outcome, nothing is stored
+    dry_run: Option<bool>,
+    /// Store nothing unless every row passes, true if omitted.
+    /// Otherwise the rows that pass are stored and the others are skipped.
+    all_or_nothing: Option<bool>,
+    /// Field delimiter of CSV files, detected from the header line if omitted
+    delimiter: Option<char>,
+    /// Quote character of CSV files, '"' if omitted
+    quote: Option<char>,
+    /// Decimal separator '.' or ',' of CSV files, detected from the rows if omitted
+    decimal: Option<char>,
+    /// Encoding of CSV files like utf-8 or windows-1252, files that aren't valid UTF-8 are read as windows-1252 if omitted
+    encoding: Option<String>,
+    /// JSON object mapping column headers of the file onto fields, e.g. {"Artikelnummer":"article_id"}
+    mapping: Option<String>,
+}
+
+// Response to an upload, the queued job and where to follow it
+type QueuedImport = (StatusCode, [(header::HeaderName, String); 1], Json<Job>);
+
+// Turns an uploaded file into rows, called by the background job
+type FileReader<T> = fn(&[u8], &CsvDialect) -> AppResult<Vec<ImportRow<<T as CsvRecord>::Row>>>;
+
 #[utoipa::path(
     post,
     path = "/import_csv",
+    params(ImportParams),
     responses(
-        (status = 200, description = "CSV imported successfully", body = serde_json::Value),
-        (status = 400, description = "Error during import", body = serde_json::Value),
-        (status = 500, description = "Internal server error", body = serde_json::Value)
+        (status = 202, description = "Import queued, its progress and report are available under /jobs/{id}", body = Job),
+        (status = 422, description = "Missing file or invalid parameter", body = serde_json::Value)
     )
 )]
-// POST /<T>/handle_import_csv
+// POST /<T>/import_csv?mode=&dry_run=&all_or_nothing=&delimiter=&quote=&decimal=&encoding=&mapping=
 pub async fn handle_import_csv<T>(
     Extension(pool): Extension<DbPool>,
-    mut multipart: Multipart,
-) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)>
+    Extension(jobs): Extension<JobQueue>,
+    Actor(actor): Actor,
+    Query(params): Query<ImportParams>,
+    multipart: Multipart,
+) -> AppResult<QueuedImport>
+where
+    T: Mappable + Insertable + CsvRecord + Debug + Serialize + 'static,
+{
+    queue_import::<T>(pool, jobs, actor, params, multipart, read_csv::<T>).await
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/import_xlsx",
+    params(ImportParams),
+    responses(
+        (status = 202, description = "Import of the entity's sheet queued, its progress and report are available under /jobs/{id}", body = Job),
+        (status = 422, description = "Missing file or invalid parameter", body = serde_json::Value)
+    )
+)]
+// POST /<T>/import_xlsx?mode=&dry_run=&all_or_nothing=&mapping=
+pub async fn handle_import_xlsx<T>(
+    Extension(pool): Extension<DbPool>,
+    Extension(jobs): Extension<JobQueue>,
+    Actor(actor): Actor,
+    Query(params): Query<ImportParams>,
+    multipart: Multipart,
+) -> AppResult<QueuedImport>
 where
-    T: Mappable + Insertable + Debug + DeserializeOwned,
+    T: Mappable + Insertable + CsvRecord + Debug + Serialize + 'static,
 {
-    let conn = crate::core::operations::establish_connection(&pool)?;
+    queue_import::<T>(pool, jobs, actor, params, multipart, read_xlsx::<T>).await
+}
 
+// Queues the import of the uploaded file, large files take longer than a request should
+async fn queue_import<T>(
+    pool: DbPool,
+    jobs: JobQueue,
+    actor: String,
+    params: ImportParams,
+    mut multipart: Multipart,
+    read: FileReader<T>,
+) -> AppResult<QueuedImport>
+where
+    T: Mappable + Insertable + CsvRecord + Debug + Serialize + 'static,
+{
     let mut file_data = None;
-    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+    let mut file_name = None;
+    while let Some(field) = multipart.next_field().await.map_err(invalid_upload)? {
         if field.name() == Some("file") {
-            file_data = Some(field.bytes().await.unwrap());
+            file_name = field.file_name().map(str::to_string);
+            file_data = Some(field.bytes().await.map_err(invalid_upload)?);
             break;
         }
     }
 
-    let data = file_data.ok_or((
-        StatusCode::BAD_REQUEST,
-        Json(json!({ "error": "Keine Datei hochgeladen" })),
-    ))?;
+    let data = file_data.ok_or(AppError::Validation("No file uploaded".into()))?;
+    let options = ImportOptions {
+        mode: params.mode.unwrap_or_default(),
+        dry_run: params.dry_run.unwrap_or(false),
+        all_or_nothing: params.all_or_nothing.unwrap_or(true),
+    };
+
+    let dialect = CsvDialect::parse(
+        params.delimiter,
+        params.quote,
+        params.decimal,
+        params.encoding.as_deref(),
+        params.mapping.as_deref(),
+    )?;
+
+    let job = jobs.submit(format!("{} import", T::entity_name()), move |progress| {
+        let rows = read(&data, &dialect)?;
+        let mut conn = establish_connection(&pool)?;
+        import_records::<T>(&mut conn, rows, options, file_name.as_deref(), &actor, progress)
+    });
+
+    let location = format!("/api/jobs/{}", job.job_id);
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(job)))
+}
 
-    let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(Cursor::new(data));
 
-    for result in rdr.deserialize() {
-        let record: T = result.map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": format!("CSV-Parsing-Fehler: {}", e) })),
-            )
-        })?;
+#[utoipa::path(
+    get,
+    path = "/imports",
+    responses(
+        (status = 200, description = "Committed imports, newest first", body = [ImportBatch]),
+        (status = 500, description = "Error fetching the imports", body = serde_json::Value)
+    )
+)]
+// GET /imports
+pub async fn handle_import_batches(
+    Extension(pool): Extension<DbPool>,
+) -> AppResult<Json<Vec<ImportBatch>>> {
+    let conn = establish_connection(&pool)?;
+    Ok(Json(fetch_import_batches(&conn, None)?))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/imports/{id}/revert",
+    params(
+        ("id" = i64, Path, description = "ID of the import batch")
+    ),
+    responses(
+        (status = 200, description = "Records inserted by the import removed and updated fields restored", body = ImportBatch),
+        (status = 404, description = "Import not found", body = serde_json::Value),
+        (status = 409, description = "Already reverted, or records changed or referenced since the import", body = serde_json::Value)
+    )
+)]
+// POST /imports/:id/revert
+pub async fn handle_revert_import(
+    Extension(pool): Extension<DbPool>,
+    Actor(actor): Actor,
+    Path(id): Path<i64>,
+) -> AppResult<Json<ImportBatch>> {
+    let mut conn = establish_connection(&pool)?;
+    let batch = in_transaction(&mut conn, |tx| revert_import_batch(tx, id, &actor))?;
+
+    Ok(Json(batch))
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    params(
+        ("id" = u32, Path, description = "ID of the job")
+    ),
+    responses(
+        (status = 200, description = "Status and progress of the job, with the report once it is done", body = Job),
+        (status = 404, description = "Job not found", body = serde_json::Value)
+    )
+)]
+// GET /jobs/:id
+pub async fn handle_job(
+    Extension(jobs): Extension<JobQueue>,
+    Path(id): Path<u32>,
+) -> AppResult<Json<Job>> {
+    Ok(Json(jobs.get(id)?))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/cancel",
+    params(
+        ("id" = u32, Path, description = "ID of the job")
+    ),
+    responses(
+        (status = 200, description = "Job cancelled, a running import stops and rolls back what it hasn't committed", body = Job),
+        (status = 404, description = "Job not found", body = serde_json::Value),
+        (status = 409, description = "Job already finished", body = serde_json::Value)
+    )
+)]
+// POST /jobs/:id/cancel
+pub async fn handle_cancel_job(
+    Extension(jobs): Extension<JobQueue>,
+    Path(id): Path<u32>,
+) -> AppResult<Json<Job>> {
+    Ok(Json(jobs.cancel(id)?))
+}
 
-        insert_record(&conn, &record).map_err(|e| {
+
+#[utoipa::path(
+    get,
+    path = "/export_csv",
+    params(
+        ("sort" = Option<String>, Query, description = "Sort order as field:asc or field:desc"),
+        ("include_deleted" = Option<bool>, Query, description = "Include soft deleted records"),
+        ("filters" = Option<HashMap<String, String>>, Query, style = Form, explode,
+            description = "Column filters as field=value, field_min=value or field_max=value")
+    ),
+    responses(
+        (status = 200, description = "CSV file in the format of the import, orders with one row per line", content_type = "text/csv"),
+        (status = 422, description = "Unknown field or invalid parameter", body = serde_json::Value)
+    )
+)]
+// GET /<T>/export_csv?sort=&<field>=
+pub async fn handle_export_csv<T>(
+    Extension(pool): Extension<DbPool>,
+    Query(params): Query<HashMap<String, String>>,
+) -> AppResult<Response>
+where
+    T: Mappable + Insertable + CsvRecord + Debug + 'static,
+{
+    let query = ListQuery::parse::<T>(&params)?;
+
+    // Pages are written on a blocking thread and sent on while the client reads them,
+    // the producer stops once the client went away and the receiver is dropped
+    let (sender, mut receiver) = mpsc::channel(2);
+    tokio::task::spawn_blocking(move || export_csv::<T>(&pool, query, &sender));
+
+    // A failing first page is reported before the response starts
+    let first = receiver
+        .recv()
+        .await
+        .ok_or_else(|| AppError::Internal("CSV export stopped".into()))??;
+    let rest = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let data = Body::from_stream(stream::once(async { Ok(first) }).chain(rest));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv".to_string()),
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": format!("Fehler beim Einfügen: {}", e) })),
-            )
-        })?;
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.csv\"", T::table_name()),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+// Records written per chunk of a CSV export
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+type CsvChunks = mpsc::Sender<AppResult<Vec<u8>>>;
+
+fn export_csv<T>(pool: &DbPool, query: ListQuery, chunks: &CsvChunks)
+where
+    T: Mappable + Insertable + CsvRecord + Debug,
+{
+    if let Err(e) = send_csv_pages::<T>(pool, query, chunks) {
+        // Fails only if the client is gone already
+        let _ = chunks.blocking_send(Err(e));
+    }
+}
+
+// All pages are read in one read transaction, so they show the same state of the
+// table even while other requests write to it. The first chunk starts with the header.
+fn send_csv_pages<T>(pool: &DbPool, mut query: ListQuery, chunks: &CsvChunks) -> AppResult<()>
+where
+    T: Mappable + Insertable + CsvRecord + Debug,
+{
+    let mut conn = establish_connection(pool)?;
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Deferred)?;
+    let end = query.limit.map(|limit| query.offset + limit);
+
+    let mut headers = true;
+    while let Some(data) = export_page::<T>(&tx, &mut query, end, headers)? {
+        headers = false;
+        if chunks.blocking_send(Ok(data)).is_err() {
+            return Ok(());
+        }
+    }
+
+    // Without records the file still has the columns of the import
+    if headers {
+        let mut writer = WriterBuilder::new().from_writer(Vec::new());
+        writer.write_record(row_fields::<T::Row>()).map_err(csv_error)?;
+        let data = writer.into_inner().map_err(|e| csv_error(e.into_error()))?;
+        let _ = chunks.blocking_send(Ok(data));
+    }
+
+    Ok(())
+}
+
+fn csv_error(e: impl std::fmt::Display) -> AppError {
+    AppError::Internal(format!("Failed to write CSV: {}", e))
+}
+
+// Next page of an export as CSV, None once all records up to `end` are written
+fn export_page<T>(
+    conn: &Connection,
+    query: &mut ListQuery,
+    end: Option<i64>,
+    headers: bool,
+) -> AppResult<Option<Vec<u8>>>
+where
+    T: Mappable + Insertable + CsvRecord + Debug,
+{
+    let limit = end.map_or(EXPORT_PAGE_SIZE, |end| {
+        (end - query.offset).min(EXPORT_PAGE_SIZE)
+    });
+    if limit <= 0 {
+        return Ok(None);
+    }
+
+    query.limit = Some(limit);
+    let page = fetch_records::<T>(conn, query)?;
+    if page.items.is_empty() {
+        return Ok(None);
     }
+    query.offset += page.items.len() as i64;
+
+    let mut writer = WriterBuilder::new()
+        .has_headers(headers)
+        .from_writer(Vec::new());
+    for row in page.items.iter().flat_map(|record| record.to_csv_rows()) {
+        writer.serialize(row).map_err(csv_error)?;
+    }
+    let data = writer.into_inner().map_err(|e| csv_error(e.into_error()))?;
+
+    Ok(Some(data))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export_xlsx",
+    responses(
+        (status = 200, description = "Workbook with a sheet each for articles, customers and orders in the format of the import",
+            content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        (status = 500, description = "Error writing the workbook", body = serde_json::Value)
+    )
+)]
+// GET /export_xlsx
+pub async fn handle_export_xlsx(Extension(pool): Extension<DbPool>) -> AppResult<Response> {
+    let conn = establish_connection(&pool)?;
+
+    let mut workbook = Workbook::new();
+    add_sheet::<Article>(&mut workbook, &conn)?;
+    add_sheet::<Customer>(&mut workbook, &conn)?;
+    add_sheet::<Order>(&mut workbook, &conn)?;
+    let data = workbook.save_to_buffer().map_err(xlsx_error)?;
 
     Ok((
-        StatusCode::OK,
-        Json(json!({ "message": "CSV successfully imported" })),
-    ))
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"inventory.xlsx\"",
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct BackupParams {
+    /// sqlite for a copy of the database file, json for a dump of all tables, sqlite if omitted
+    format: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/admin/backup",
+    params(BackupParams),
+    responses(
+        (status = 200, description = "Consistent snapshot of the database, or a JSON dump of all tables"),
+        (status = 422, description = "Unknown format", body = serde_json::Value),
+        (status = 500, description = "Error taking the backup", body = serde_json::Value)
+    )
+)]
+// GET /admin/backup?format=
+pub async fn handle_backup(
+    Extension(pool): Extension<DbPool>,
+    Query(params): Query<BackupParams>,
+) -> AppResult<Response> {
+    let (content_type, extension, data) = match params.format.as_deref().unwrap_or("sqlite") {
+        "sqlite" => {
+            let file = run_blocking(move || {
+                let conn = establish_connection(&pool)?;
+                backup_to_temp_file(&conn)
+            })
+            .await?;
+            ("application/vnd.sqlite3", "db", stream_temp_file(file).await?)
+        }
+        "json" => {
+            let data = run_blocking(move || {
+                let conn = establish_connection(&pool)?;
+                serde_json::to_vec(&dump(&conn)?).map_err(|e| AppError::Internal(e.to_string()))
+            })
+            .await?;
+            ("application/json", "json", Body::from(data))
+        }
+        format => {
+            return Err(AppError::Validation(format!(
+                "Unknown backup format {}, expected sqlite or json",
+                format
+            )))
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"inventory-backup.{}\"", extension),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+// Runs blocking work like SQLite calls off the async runtime
+async fn run_blocking<R>(work: impl FnOnce() -> AppResult<R> + Send + 'static) -> AppResult<R>
+where
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+}
+
+// Body reading the file in chunks, the file is removed once the body is dropped
+async fn stream_temp_file(file: TempFile) -> AppResult<Body> {
+    let reader = tokio::fs::File::open(file.path())
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read backup: {}", e)))?;
+
+    let chunks = stream::unfold(
+        (ReaderStream::new(reader), file),
+        |(mut reader, file)| async move {
+            reader.next().await.map(|chunk| (chunk, (reader, file)))
+        },
+    );
+    Ok(Body::from_stream(chunks))
+}
+
+
+#[utoipa::path(
+    post,
+    path = "/admin/restore",
+    responses(
+        (status = 200, description = "Data replaced by the backup, the previous data is kept as a pre-restore backup", body = serde_json::Value),
+        (status = 422, description = "Missing file, or the backup is damaged, of a newer version or has broken references", body = serde_json::Value),
+        (status = 500, description = "Error restoring the backup", body = serde_json::Value)
+    )
+)]
+// POST /admin/restore
+pub async fn handle_restore(
+    Extension(pool): Extension<DbPool>,
+    Extension(backups): Extension<BackupStore>,
+    mut multipart: Multipart,
+) -> AppResult<Json<serde_json::Value>> {
+    let mut file_data = None;
+    while let Some(field) = multipart.next_field().await.map_err(invalid_upload)? {
+        if field.name() == Some("file") {
+            file_data = Some(field.bytes().await.map_err(invalid_upload)?);
+            break;
+        }
+    }
+    let data = file_data.ok_or(AppError::Validation("No file uploaded".into()))?;
+
+    let previous = run_blocking(move || {
+        let mut conn = establish_connection(&pool)?;
+
+        // Checked up front, so an invalid upload doesn't leave a needless backup behind
+        let upload = if data.starts_with(b"SQLite format 3") {
+            Upload::Backup(check_backup(&data)?)
+        } else {
+            let json_dump = serde_json::from_slice::<Dump>(&data).map_err(|e| {
+                AppError::Validation(format!("The file is neither a backup nor a dump: {}", e))
+            })?;
+            check_dump(&conn, &json_dump)?;
+            Upload::Dump(json_dump)
+        };
+
+        let previous = backups.create(&conn, BackupKind::PreRestore)?;
+
+        match upload {
+            Upload::Backup(backup) => restore_backup(&mut conn, &backup)?,
+            Upload::Dump(json_dump) => restore_dump(&mut conn, &json_dump)?,
+        }
+
+        Ok(previous)
+    })
+    .await?;
+
+    Ok(Json(json!({
+        "message": "Backup restored successfully",
+        "previous": previous.file_name().map(|name| name.to_string_lossy()),
+    })))
+}
+
+// Uploaded file of a restore, after it has been checked
+enum Upload {
+    Backup(CheckedBackup),
+    Dump(Dump),
+}
+
+fn invalid_upload(e: axum::extract::multipart::MultipartError) -> AppError {
+    AppError::Validation(format!("Invalid upload: {}", e))
+}
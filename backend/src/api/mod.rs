@@ -1,3 +1,4 @@
+pub mod actor;
 pub mod endpoint;
 pub mod routes;
 pub mod server;
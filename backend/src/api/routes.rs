@@ -1,19 +1,23 @@
 //routes.rs
 use crate::api::endpoint::{
     handle_create_record, handle_fetch_records, handle_generate_pdf,
-    handle_import_csv, handle_search, handle_update_record, handle_delete_record
+    handle_import_csv, handle_search, handle_text_search, handle_update_record,
+    handle_delete_record, handle_restore_record, handle_change_order_status,
+    handle_status_history, handle_patch_record, handle_export_csv, handle_job,
+    handle_cancel_job, handle_import_batches, handle_revert_import, handle_import_xlsx,
+    handle_export_xlsx, handle_backup, handle_restore
 };
 use crate::api;
 
-use crate::core::types::{ApiResponse, Article, ArticleStatistics, Customer, CustomerStatistics, Order, OrderItem, OrderStatistics, OrderStatus, OrderType, Statistics};
+use crate::core::types::{ApiResponse, Article, AuditEntry, ArticleStatistics, Customer, CustomerStatistics, Order, OrderItem, OrderStatistics, OrderStatus, OrderType, Statistics, StatusChange, ImportBatch, ImportReport, Job, JobStatus, RowAction, RowReport, RowStatus};
 use axum::{
-    routing::{delete, get, post, put},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use super::endpoint::handle_statistics;
+use super::endpoint::{handle_audit_log, handle_statistics};
 
 
 #[derive(OpenApi)]
@@ -21,15 +25,30 @@ use super::endpoint::handle_statistics;
     tags(
         (name = "InventoryManager", description = "Documentation of the REST-API of InventoryManager.")
     ),
-    components(schemas(Statistics, ApiResponse, Article, Customer, Order, OrderItem, OrderType, OrderStatus, CustomerStatistics, ArticleStatistics, OrderStatistics)),
+    components(schemas(Statistics, ApiResponse, AuditEntry, StatusChange, ImportBatch, ImportReport, Job, JobStatus, RowAction, RowReport, RowStatus, api::endpoint::StatusRequest, Article, Customer, Order, OrderItem, OrderType, OrderStatus, CustomerStatistics, ArticleStatistics, OrderStatistics)),
     paths(
         api::endpoint::handle_search,
+        api::endpoint::handle_text_search,
         api::endpoint::handle_fetch_records,
         api::endpoint::handle_create_record,
         api::endpoint::handle_update_record,
+        api::endpoint::handle_patch_record,
+        api::endpoint::handle_restore_record,
+        api::endpoint::handle_change_order_status,
+        api::endpoint::handle_status_history,
         api::endpoint::handle_statistics,
+        api::endpoint::handle_audit_log,
         api::endpoint::handle_generate_pdf,
-        api::endpoint::handle_import_csv
+        api::endpoint::handle_import_csv,
+        api::endpoint::handle_import_xlsx,
+        api::endpoint::handle_import_batches,
+        api::endpoint::handle_revert_import,
+        api::endpoint::handle_job,
+        api::endpoint::handle_cancel_job,
+        api::endpoint::handle_export_csv,
+        api::endpoint::handle_export_xlsx,
+        api::endpoint::handle_backup,
+        api::endpoint::handle_restore
     )
 )]
 pub struct ApiDoc;
@@ -38,6 +57,26 @@ pub fn operation_routes() -> Router {
     Router::new()
         .route("/operations/pdf", post(handle_generate_pdf))
         .route("/operations/statistics", get(handle_statistics))
+        .route("/audit", get(handle_audit_log))
+        .route("/export_xlsx", get(handle_export_xlsx))
+}
+
+pub fn admin_routes() -> Router {
+    Router::new()
+        .route("/admin/backup", get(handle_backup))
+        .route("/admin/restore", post(handle_restore))
+}
+
+pub fn import_routes() -> Router {
+    Router::new()
+        .route("/imports", get(handle_import_batches))
+        .route("/imports/:id/revert", post(handle_revert_import))
+}
+
+pub fn job_routes() -> Router {
+    Router::new()
+        .route("/jobs/:id", get(handle_job))
+        .route("/jobs/:id/cancel", post(handle_cancel_job))
 }
 
 pub fn article_routes() -> Router {
@@ -49,8 +88,13 @@ pub fn article_routes() -> Router {
             "/articles/delete/:id",
             delete(handle_delete_record::<Article>),
         )
+        .route("/articles/restore/:id", post(handle_restore_record::<Article>))
         .route("/articles/update", put(handle_update_record::<Article>))
+        .route("/articles/:id", patch(handle_patch_record::<Article>))
         .route("/articles/import_csv", post(handle_import_csv::<Article>))
+        .route("/articles/import_xlsx", post(handle_import_xlsx::<Article>))
+        .route("/articles/export_csv", get(handle_export_csv::<Article>))
+        .route("/articles/search", get(handle_text_search::<Article>))
         .route("/articles/search/:id", get(handle_search::<Article>))
 }
 
@@ -66,8 +110,16 @@ pub fn customer_routes() -> Router {
             "/customers/delete/:id",
             delete(handle_delete_record::<Customer>),
         )
+        .route(
+            "/customers/restore/:id",
+            post(handle_restore_record::<Customer>),
+        )
         .route("/customers/update", put(handle_update_record::<Customer>))
+        .route("/customers/:id", patch(handle_patch_record::<Customer>))
         .route("/customers/import_csv", post(handle_import_csv::<Customer>))
+        .route("/customers/import_xlsx", post(handle_import_xlsx::<Customer>))
+        .route("/customers/export_csv", get(handle_export_csv::<Customer>))
+        .route("/customers/search", get(handle_text_search::<Customer>))
         .route("/customers/search/:id", get(handle_search::<Customer>))
 }
 
@@ -77,7 +129,14 @@ pub fn order_routes() -> Router {
         .route("/orders/add", post(handle_create_record::<Order>))
         .route("/orders/delete", delete(handle_delete_record::<Order>))
         .route("/orders/delete/:id", delete(handle_delete_record::<Order>))
+        .route("/orders/restore/:id", post(handle_restore_record::<Order>))
         .route("/orders/update", put(handle_update_record::<Order>))
+        .route("/orders/:id", patch(handle_patch_record::<Order>))
+        .route("/orders/:id/status", post(handle_change_order_status))
+        .route("/orders/:id/status_history", get(handle_status_history))
+        .route("/orders/import_csv", post(handle_import_csv::<Order>))
+        .route("/orders/import_xlsx", post(handle_import_xlsx::<Order>))
+        .route("/orders/export_csv", get(handle_export_csv::<Order>))
         .route("/orders/search/:id", get(handle_search::<Order>))
 }
 
@@ -87,5 +146,8 @@ pub fn get_routes() -> Router {
         .nest("/api", article_routes())
         .nest("/api", customer_routes())
         .nest("/api", order_routes())
+        .nest("/api", admin_routes())
+        .nest("/api", import_routes())
+        .nest("/api", job_routes())
         .merge(SwaggerUi::new("/api/docs").url("/api-docs/openapi.json", ApiDoc::openapi()))
 }
@@ -1,9 +1,16 @@
 //server.rs
 use crate::api::routes;
-use crate::core::operations::initialize_tables;
+use crate::core::backup::{
+    BackupKind, BackupStore, Backups, BACKUP_INTERVAL, DEFAULT_KEPT_BACKUPS,
+};
+use crate::core::jobs::Jobs;
+use crate::core::operations::{establish_connection, DeletionTokens, DELETION_TOKEN_TTL};
+use crate::core::migrations::run_migrations;
+use crate::core::types::DbPool;
 use axum::{Extension, Router};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::env;
 use std::fs;
@@ -17,28 +24,92 @@ fn get_project_root() -> PathBuf {
 
 
 
-pub async fn start_api_server() {
+// Database and backups live in data/ in the project root
+fn data_dir() -> PathBuf {
+    get_project_root().join("data")
+}
+
+// Creates the SQLite connection pool for data/database.db in the project root
+fn create_pool() -> DbPool {
     // DB Path
-    let project_root = get_project_root();
-    let db_path = project_root.join("data/database.db");
-    let db_dir = db_path.parent().unwrap(); 
+    let db_dir = data_dir();
+    let db_path = db_dir.join("database.db");
 
     if !db_dir.exists() {
-        fs::create_dir_all(db_dir).expect("Failed to create data directory");
+        fs::create_dir_all(&db_dir).expect("Failed to create data directory");
     }
 
+    // SQLite Connection Pool, foreign keys are off by default in SQLite.
+    // In WAL mode readers don't block writers, e.g. while a CSV export reads all pages
+    // of a table in one transaction.
+    let manager = SqliteConnectionManager::file(db_path).with_init(|conn| {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))
+    });
+    Arc::new(Pool::builder().max_size(5).build(manager).unwrap())
+}
 
-    
-    // SQLite Connection Pool
-    let manager = SqliteConnectionManager::file(db_path);
-    let pool = Arc::new(Pool::builder().max_size(5).build(manager).unwrap());
+// Brings the database schema up to date, used at startup and by `backend migrate`
+pub fn migrate_database() -> Result<i32, Box<dyn std::error::Error>> {
+    let pool = create_pool();
+    let mut conn = pool.get()?;
+
+    Ok(run_migrations(&mut conn)?)
+}
+
+// Number of automatic backups to keep, set with BACKUP_KEEP
+fn kept_backups() -> Result<usize, String> {
+    match env::var("BACKUP_KEEP") {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|&kept| kept > 0)
+            .ok_or_else(|| format!("BACKUP_KEEP must be a positive number, got '{}'", value)),
+        Err(_) => Ok(DEFAULT_KEPT_BACKUPS),
+    }
+}
+
+// Takes an automatic backup right away and then every BACKUP_INTERVAL
+async fn schedule_backups(pool: DbPool, backups: BackupStore) {
+    let mut interval = tokio::time::interval(BACKUP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        let backups = backups.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let conn = establish_connection(&pool)?;
+            backups.create(&conn, BackupKind::Automatic)
+        })
+        .await;
+
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Automatic backup failed: {}", e),
+            Err(e) => eprintln!("Automatic backup failed: {}", e),
+        }
+    }
+}
+
+pub async fn start_api_server() {
+    let pool = create_pool();
 
-    let conn = pool.get().expect("Failed to get connection from pool");
+    let mut conn = pool.get().expect("Failed to get connection from pool");
 
-    if let Err(e) = initialize_tables(&conn) {
-        eprintln!("Failed to create table: {}", e);
+    // Serving on a partly migrated schema would fail in confusing ways later on
+    if let Err(e) = run_migrations(&mut conn) {
+        eprintln!("Failed to migrate database: {}", e);
+        std::process::exit(1);
     }
+    drop(conn);
 
+    let kept = kept_backups().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let backups = Arc::new(Backups::new(data_dir().join("backups"), kept));
+    tokio::spawn(schedule_backups(pool.clone(), backups.clone()));
 
     let cors = CorsLayer::new()
     .allow_origin(Any)  
@@ -49,11 +120,20 @@ pub async fn start_api_server() {
     let app = Router::new()
         .merge(routes::get_routes())
         .layer(cors.clone())
-        .layer(Extension(pool));
+        .layer(Extension(pool))
+        .layer(Extension(backups))
+        .layer(Extension(Arc::new(DeletionTokens::new(DELETION_TOKEN_TTL))))
+        .layer(Extension(Arc::new(Jobs::new())));
 
     let listener = tokio::net::TcpListener::bind("127.0.0.1:8080")
         .await.unwrap_or_else(|_| panic!("Unable to listen on 127.0.0.1:8080"));
 
-    axum::serve(listener, app).await.unwrap();
+    // Client addresses are needed to attribute changes in the audit log
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
     
 }
@@ -0,0 +1,104 @@
+// audit.rs
+use crate::core::error::{AppError, AppResult};
+use crate::core::operations::find_record_by_id;
+use crate::core::traits::{Insertable, Mappable};
+use crate::core::types::AuditEntry;
+use rusqlite::{params, Connection, Transaction};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use strum_macros::Display;
+
+#[derive(Debug, Clone, Copy, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+// Current state of a record as JSON, used as before/after value of an audit entry
+pub fn snapshot<T: Mappable + Insertable + Serialize>(
+    conn: &Connection,
+    id_value: i32,
+) -> AppResult<Value> {
+    to_snapshot(&find_record_by_id::<T>(conn, id_value)?)
+}
+
+pub fn to_snapshot<T: Serialize>(record: &T) -> AppResult<Value> {
+    serde_json::to_value(record).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Field-level diff between two snapshots, containing only the changed fields
+/// as `{ "field": { "before": ..., "after": ... } }`
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+
+        if old != new && !changes.contains_key(key) {
+            changes.insert(key.clone(), json!({ "before": old, "after": new }));
+        }
+    }
+
+    Value::Object(changes)
+}
+
+pub fn record_audit<T: Insertable>(
+    tx: &Transaction,
+    id_value: i32,
+    action: AuditAction,
+    actor: &str,
+    before: &Value,
+    after: &Value,
+) -> AppResult<()> {
+    tx.execute(
+        "INSERT INTO audit_log (entity, entity_id, action, actor, timestamp, changes)
+         VALUES (?1, ?2, ?3, ?4, datetime('now'), ?5)",
+        params![
+            T::entity_name(),
+            id_value,
+            action.to_string(),
+            actor,
+            diff(before, after).to_string()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Audit entries, newest first, optionally limited to one entity type and ID
+pub fn fetch_audit_log(
+    conn: &Connection,
+    entity: Option<&str>,
+    entity_id: Option<i32>,
+    limit: i64,
+) -> AppResult<Vec<AuditEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, entity, entity_id, action, actor, timestamp, changes
+         FROM audit_log
+         WHERE (?1 IS NULL OR entity = ?1) AND (?2 IS NULL OR entity_id = ?2)
+         ORDER BY id DESC
+         LIMIT ?3",
+    )?;
+
+    let rows = stmt.query_map(params![entity, entity_id, limit], |row| {
+        let changes: String = row.get(6)?;
+        Ok(AuditEntry::new(
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            serde_json::from_str(&changes).unwrap_or(Value::Null),
+        ))
+    })?;
+
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
@@ -0,0 +1,482 @@
+// backup.rs
+use crate::core::error::{AppError, AppResult};
+use crate::core::migrations::{current_version, latest_version, run_migrations};
+use rusqlite::backup::Progress;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, DatabaseName, ErrorCode, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Automatic backups are taken at startup and then once a day
+pub const BACKUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Automatic backups kept unless configured otherwise, older ones are deleted
+pub const DEFAULT_KEPT_BACKUPS: usize = 7;
+
+// Tables of a JSON dump, parents before the tables referencing them
+const DUMP_TABLES: &[&str] = &[
+    "article",
+    "customer",
+    "orders",
+    "order_article",
+    "order_status_history",
+    "audit_log",
+    "import_batch",
+    "import_batch_record",
+];
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// All rows of the data tables together with the schema version they belong to
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Dump {
+    pub schema_version: i32,
+    pub tables: BTreeMap<String, Vec<Map<String, Value>>>,
+}
+
+/// Why a backup is taken, which decides its file name and whether it is rotated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupKind {
+    // Taken on schedule, only the latest ones are kept
+    Automatic,
+    // Taken before a restore replaces the data, kept until deleted by hand
+    PreRestore,
+}
+
+impl BackupKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            BackupKind::Automatic => "backup-",
+            BackupKind::PreRestore => "pre-restore-",
+        }
+    }
+}
+
+/// Backups in the data directory, of the automatic ones only the latest are kept
+pub struct Backups {
+    dir: PathBuf,
+    kept: usize,
+}
+
+pub type BackupStore = Arc<Backups>;
+
+impl Backups {
+    pub fn new(dir: PathBuf, kept: usize) -> Self {
+        Backups { dir, kept }
+    }
+
+    /// Takes a backup named after the current time. Existing files are never
+    /// overwritten, and only automatic backups are rotated.
+    pub fn create(&self, conn: &Connection, kind: BackupKind) -> AppResult<PathBuf> {
+        fs::create_dir_all(&self.dir).map_err(io_error)?;
+
+        // Millisecond resolution, e.g. 20240115-093012345
+        let timestamp: String = conn.query_row(
+            "SELECT replace(strftime('%Y%m%d-%H%M%f', 'now'), '.', '')",
+            [],
+            |row| row.get(0),
+        )?;
+
+        // The name is claimed by creating the file, so two backups in the same
+        // millisecond get different names instead of overwriting each other.
+        // The suffix keeps them sorted by time.
+        let mut path = self.dir.join(format!("{}{}.db", kind.prefix(), timestamp));
+        let mut attempt = 0;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < 99 => {
+                    attempt += 1;
+                    path = self
+                        .dir
+                        .join(format!("{}{}_{:02}.db", kind.prefix(), timestamp, attempt));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+
+        if let Err(e) = backup_to_file(conn, &path) {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+
+        if kind == BackupKind::Automatic {
+            self.rotate()?;
+        }
+
+        Ok(path)
+    }
+
+    // Deletes the oldest automatic backups beyond the configured number
+    fn rotate(&self) -> AppResult<()> {
+        let prefix = BackupKind::Automatic.prefix();
+
+        // Names sort by time
+        let mut backups: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .map_err(io_error)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(prefix) && name.ends_with(".db"))
+            })
+            .collect();
+        backups.sort();
+
+        for old in backups.iter().take(backups.len().saturating_sub(self.kept)) {
+            fs::remove_file(old).map_err(io_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Consistent copy of the database made with SQLite's online backup API,
+/// other connections can keep writing meanwhile
+pub fn backup_to_file(conn: &Connection, path: &Path) -> AppResult<()> {
+    conn.backup(DatabaseName::Main, path, None)?;
+    Ok(())
+}
+
+/// Snapshot of the database in a temporary file, e.g. to be downloaded
+pub fn backup_to_temp_file(conn: &Connection) -> AppResult<TempFile> {
+    let file = TempFile::new("backup");
+    backup_to_file(conn, &file.0)?;
+    Ok(file)
+}
+
+/// An uploaded backup that passed all checks, migrated to the current schema
+pub struct CheckedBackup {
+    file: TempFile,
+}
+
+/// Checks an uploaded backup for damage and broken references and migrates a copy
+/// of it to the current schema, without touching the database
+pub fn check_backup(data: &[u8]) -> AppResult<CheckedBackup> {
+    if !data.starts_with(SQLITE_HEADER) {
+        return Err(AppError::Validation(
+            "The file is not an SQLite database".into(),
+        ));
+    }
+
+    let file = TempFile::new("restore");
+    fs::write(&file.0, data).map_err(io_error)?;
+
+    let mut source = Connection::open(&file.0)?;
+    let integrity: String = source
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(invalid_backup)?;
+    if integrity != "ok" {
+        return Err(AppError::Validation(format!(
+            "The backup is damaged: {}",
+            integrity
+        )));
+    }
+
+    let version = current_version(&source).map_err(invalid_backup)?;
+    if version > latest_version() {
+        return Err(AppError::Validation(format!(
+            "The backup has schema version {}, newer than this version of the application",
+            version
+        )));
+    }
+
+    run_migrations(&mut source).map_err(invalid_backup)?;
+    let violations: i64 =
+        source.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })?;
+    if violations > 0 {
+        return Err(AppError::Validation(format!(
+            "The backup has {} rows referencing records that don't exist",
+            violations
+        )));
+    }
+
+    Ok(CheckedBackup { file })
+}
+
+/// Replaces the database with a checked backup
+pub fn restore_backup(conn: &mut Connection, backup: &CheckedBackup) -> AppResult<()> {
+    conn.restore(DatabaseName::Main, &backup.file.0, None::<fn(Progress)>)?;
+    Ok(())
+}
+
+/// Rows of all data tables, e.g. to inspect or edit them before restoring
+pub fn dump(conn: &Connection) -> AppResult<Dump> {
+    let mut tables = BTreeMap::new();
+
+    for table in DUMP_TABLES {
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {} ORDER BY rowid", table))?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+        let rows = stmt
+            .query_map([], |row| {
+                let mut record = Map::new();
+                for (index, column) in columns.iter().enumerate() {
+                    record.insert(column.clone(), to_json(row.get_ref(index)?));
+                }
+                Ok(record)
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        tables.insert(table.to_string(), rows);
+    }
+
+    Ok(Dump {
+        schema_version: current_version(conn)?,
+        tables,
+    })
+}
+
+/// Checks that a dump matches the schema of the database, without touching the database
+pub fn check_dump(conn: &Connection, dump: &Dump) -> AppResult<()> {
+    let version = current_version(conn)?;
+    if dump.schema_version != version {
+        return Err(AppError::Validation(format!(
+            "The dump has schema version {}, but the database has version {}",
+            dump.schema_version, version
+        )));
+    }
+
+    if let Some(table) = dump
+        .tables
+        .keys()
+        .find(|table| !DUMP_TABLES.contains(&table.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "Unknown table {} in the dump",
+            table
+        )));
+    }
+
+    Ok(())
+}
+
+/// Replaces the rows of all data tables with the ones of the dump in one transaction.
+/// Tables missing from the dump end up empty.
+pub fn restore_dump(conn: &mut Connection, dump: &Dump) -> AppResult<()> {
+    check_dump(conn, dump)?;
+
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    // References are checked on commit, once all tables are filled
+    tx.pragma_update(None, "defer_foreign_keys", true)?;
+
+    for table in DUMP_TABLES.iter().rev() {
+        tx.execute(&format!("DELETE FROM {}", table), [])?;
+    }
+
+    for table in DUMP_TABLES {
+        let columns = {
+            let mut stmt = tx.prepare("SELECT name FROM pragma_table_info(?1)")?;
+            let names = stmt.query_map([table], |row| row.get::<_, String>(0))?;
+            names.collect::<Result<Vec<_>, _>>()?
+        };
+
+        for (index, row) in dump.tables.get(*table).into_iter().flatten().enumerate() {
+            let invalid = |reason: String| {
+                AppError::Validation(format!("Row {} of table {}: {}", index + 1, table, reason))
+            };
+
+            if let Some(column) = row.keys().find(|column| !columns.contains(column)) {
+                return Err(invalid(format!("unknown column {}", column)));
+            }
+
+            let values = row
+                .values()
+                .map(|value| {
+                    from_json(value).ok_or_else(|| invalid(format!("invalid value {}", value)))
+                })
+                .collect::<AppResult<Vec<_>>>()?;
+
+            let names: Vec<&str> = row.keys().map(String::as_str).collect();
+            let query = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                names.join(", "),
+                vec!["?"; names.len()].join(", ")
+            );
+
+            tx.execute(&query, params_from_iter(values))
+                .map_err(|e| match e {
+                    rusqlite::Error::SqliteFailure(ref err, _)
+                        if err.code == ErrorCode::ConstraintViolation =>
+                    {
+                        invalid(e.to_string())
+                    }
+                    e => e.into(),
+                })?;
+        }
+    }
+
+    tx.commit().map_err(|e| match AppError::from(e) {
+        AppError::Validation(_) => {
+            AppError::Validation("The dump has rows referencing records that don't exist".into())
+        }
+        e => e,
+    })
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(number) => number.into(),
+        ValueRef::Real(number) => number.into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(bytes) => bytes.to_vec().into(),
+    }
+}
+
+// Arrays of bytes are blobs, like the dump writes them
+fn from_json(value: &Value) -> Option<SqlValue> {
+    match value {
+        Value::Null => Some(SqlValue::Null),
+        Value::Bool(value) => Some(SqlValue::Integer(*value as i64)),
+        Value::Number(number) => number
+            .as_i64()
+            .map(SqlValue::Integer)
+            .or_else(|| number.as_f64().map(SqlValue::Real)),
+        Value::String(text) => Some(SqlValue::Text(text.clone())),
+        Value::Array(bytes) => bytes
+            .iter()
+            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .map(SqlValue::Blob),
+        Value::Object(_) => None,
+    }
+}
+
+fn invalid_backup(e: rusqlite::Error) -> AppError {
+    AppError::Validation(format!("The file is not a valid backup: {}", e))
+}
+
+fn io_error(e: std::io::Error) -> AppError {
+    AppError::Internal(format!("Failed to access backup file: {}", e))
+}
+
+/// File in the temp directory, removed once it is dropped
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn new(purpose: &str) -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let name = format!(
+            "inventory-{}-{}-{}.db",
+            purpose,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        TempFile(std::env::temp_dir().join(name))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::migrations::tests::baseline_database;
+    use std::collections::BTreeSet;
+
+    fn backup_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "inventory-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn backups_never_overwrite_each_other() {
+        let dir = backup_dir("unique");
+        let backups = Backups::new(dir.clone(), 2);
+        let conn = Connection::open_in_memory().unwrap();
+
+        let paths = (0..5)
+            .map(|_| backups.create(&conn, BackupKind::PreRestore).unwrap())
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(paths.len(), 5);
+        assert_eq!(file_names(&dir).len(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_automatic_backups_are_rotated() {
+        let dir = backup_dir("rotation");
+        let backups = Backups::new(dir.clone(), 2);
+        let conn = Connection::open_in_memory().unwrap();
+
+        let automatic = (0..4)
+            .map(|_| backups.create(&conn, BackupKind::Automatic).unwrap())
+            .collect::<Vec<_>>();
+        let pre_restore = backups.create(&conn, BackupKind::PreRestore).unwrap();
+        backups.create(&conn, BackupKind::Automatic).unwrap();
+
+        let names = file_names(&dir);
+        assert_eq!(names.len(), 3);
+        assert!(pre_restore.exists());
+        assert!(!automatic[2].exists());
+        assert!(automatic[3].exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backups_from_before_versioning_are_migrated() {
+        let file = TempFile::new("baseline");
+        backup_to_file(&baseline_database(), &file.0).unwrap();
+        let backup = check_backup(&fs::read(&file.0).unwrap()).unwrap();
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        restore_backup(&mut conn, &backup).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        let unit_price: f64 = conn
+            .query_row(
+                "SELECT unit_price FROM order_article WHERE article_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(unit_price, 12.5);
+    }
+
+    #[test]
+    fn invalid_uploads_are_rejected_before_restoring() {
+        assert!(matches!(
+            check_backup(b"not a database"),
+            Err(AppError::Validation(_))
+        ));
+
+        let mut data = SQLITE_HEADER.to_vec();
+        data.extend_from_slice(&[0xff; 200]);
+        assert!(matches!(check_backup(&data), Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,289 @@
+// error.rs
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json as AxumJson, Response},
+};
+use serde_json::json;
+use std::fmt::{self, Display};
+
+/// Errors returned by core operations and API handlers.
+/// Every variant maps to a stable error code and HTTP status.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    DuplicateId(String),
+    InsufficientStock {
+        article_id: i32,
+        requested: i32,
+        available: i32,
+    },
+    InvalidTransition {
+        from: String,
+        to: String,
+    },
+    Referenced {
+        entity: String,
+        id_value: i32,
+        order_ids: Vec<i32>,
+    },
+    ConfirmationRequired {
+        token: String,
+    },
+    // The request is valid but clashes with the current state of the data
+    Conflict(String),
+    Validation(String),
+    Db(rusqlite::Error),
+    Pool(r2d2::Error),
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::DuplicateId(_) => "duplicate_id",
+            AppError::InsufficientStock { .. } => "insufficient_stock",
+            AppError::InvalidTransition { .. } => "invalid_transition",
+            AppError::Referenced { .. } => "referenced",
+            AppError::ConfirmationRequired { .. } => "confirmation_required",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) => "validation",
+            AppError::Db(_) | AppError::Pool(_) => "db",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::DuplicateId(_)
+            | AppError::InsufficientStock { .. }
+            | AppError::InvalidTransition { .. }
+            | AppError::Referenced { .. }
+            | AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::ConfirmationRequired { .. } => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Db(_) | AppError::Pool(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::DuplicateId(msg)
+            | AppError::Conflict(msg)
+            | AppError::Validation(msg) => write!(f, "{}", msg),
+            AppError::InsufficientStock {
+                article_id,
+                requested,
+                available,
+            } => write!(
+                f,
+                "Insufficient stock for article {}: requested {}, available {}",
+                article_id, requested, available
+            ),
+            AppError::InvalidTransition { from, to } => {
+                write!(f, "Invalid status transition from {} to {}", from, to)
+            }
+            AppError::Referenced {
+                entity,
+                id_value,
+                order_ids,
+            } => write!(
+                f,
+                "{} {} is referenced by orders {}, delete with cascade=true to delete them as well",
+                entity,
+                id_value,
+                order_ids
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            AppError::ConfirmationRequired { token } => {
+                write!(f, "Deleting all records requires confirm={}", token)
+            }
+            AppError::Db(e) => write!(f, "Database error: {}", e),
+            AppError::Pool(e) => write!(f, "Failed to get connection: {}", e),
+            AppError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// Extended result codes of SQLite, not part of the generated bindings of the system library
+pub(crate) const SQLITE_CONSTRAINT_FOREIGNKEY: i32 = 787;
+const SQLITE_CONSTRAINT_PRIMARYKEY: i32 = 1555;
+const SQLITE_CONSTRAINT_UNIQUE: i32 = 2067;
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::NotFound("Record not found".into()),
+            rusqlite::Error::SqliteFailure(ref err, _) => match err.extended_code {
+                SQLITE_CONSTRAINT_UNIQUE | SQLITE_CONSTRAINT_PRIMARYKEY => {
+                    AppError::DuplicateId("Item ID is already being used".into())
+                }
+                SQLITE_CONSTRAINT_FOREIGNKEY => {
+                    AppError::Validation("A referenced record does not exist".into())
+                }
+                _ => AppError::Db(e),
+            },
+            e => AppError::Db(e),
+        }
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(e: r2d2::Error) -> Self {
+        AppError::Pool(e)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let mut body = json!({ "error": self.to_string(), "code": self.code() });
+
+        // Values a client needs to act on the error
+        match &self {
+            AppError::Referenced { order_ids, .. } => body["order_ids"] = json!(order_ids),
+            AppError::ConfirmationRequired { token } => body["token"] = json!(token),
+            _ => {}
+        }
+
+        (self.status(), AxumJson(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use rusqlite::Connection;
+    use serde_json::Value;
+
+    fn constraint_error(sql: &str) -> AppError {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            PRAGMA foreign_keys = ON;
+            CREATE TABLE parent (id INTEGER PRIMARY KEY, code INTEGER UNIQUE);
+            CREATE TABLE child (parent_id INTEGER REFERENCES parent (id));
+            INSERT INTO parent (id, code) VALUES (1, 1);
+            ",
+        )
+        .unwrap();
+        conn.execute_batch(sql).unwrap_err().into()
+    }
+
+    #[test]
+    fn errors_map_to_their_code_and_status() {
+        let cases = [
+            (
+                AppError::NotFound("".into()),
+                "not_found",
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                AppError::DuplicateId("".into()),
+                "duplicate_id",
+                StatusCode::CONFLICT,
+            ),
+            (
+                AppError::InsufficientStock {
+                    article_id: 1,
+                    requested: 2,
+                    available: 1,
+                },
+                "insufficient_stock",
+                StatusCode::CONFLICT,
+            ),
+            (
+                AppError::InvalidTransition {
+                    from: "Delivered".into(),
+                    to: "Pending".into(),
+                },
+                "invalid_transition",
+                StatusCode::CONFLICT,
+            ),
+            (
+                AppError::Conflict("".into()),
+                "conflict",
+                StatusCode::CONFLICT,
+            ),
+            (
+                AppError::ConfirmationRequired { token: "".into() },
+                "confirmation_required",
+                StatusCode::PRECONDITION_REQUIRED,
+            ),
+            (
+                AppError::Validation("".into()),
+                "validation",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                AppError::Db(rusqlite::Error::InvalidQuery),
+                "db",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                AppError::Internal("".into()),
+                "internal",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+
+        for (error, code, status) in cases {
+            assert_eq!(error.code(), code);
+            assert_eq!(error.status(), status, "{}", code);
+        }
+    }
+
+    #[test]
+    fn sqlite_errors_are_classified() {
+        assert!(matches!(
+            constraint_error("INSERT INTO parent (id, code) VALUES (1, 2)"),
+            AppError::DuplicateId(_)
+        ));
+        assert!(matches!(
+            constraint_error("INSERT INTO parent (id, code) VALUES (2, 1)"),
+            AppError::DuplicateId(_)
+        ));
+        assert!(matches!(
+            constraint_error("INSERT INTO child (parent_id) VALUES (9)"),
+            AppError::Validation(_)
+        ));
+        assert!(matches!(
+            constraint_error("SELECT * FROM missing"),
+            AppError::Db(_)
+        ));
+        assert!(matches!(
+            AppError::from(rusqlite::Error::QueryReturnedNoRows),
+            AppError::NotFound(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn responses_carry_code_message_and_details() {
+        let response = AppError::Referenced {
+            entity: "article".into(),
+            id_value: 1,
+            order_ids: vec![3, 4],
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "referenced");
+        assert_eq!(body["order_ids"], serde_json::json!([3, 4]));
+        assert!(body["error"].as_str().unwrap().contains("article 1"));
+    }
+}
@@ -0,0 +1,1104 @@
+// import.rs
+use crate::core::audit::to_snapshot;
+use crate::core::error::{AppError, AppResult};
+use crate::core::jobs::JobState;
+use crate::core::operations::{
+    delete_record_by_id, find_record_by_id, insert_record, not_found, purge_record, update_record,
+    DeletePolicy,
+};
+use crate::core::traits::{CsvRecord, Insertable, Mappable};
+use crate::core::types::{
+    Article, Customer, ImportBatch, ImportReport, Order, RowAction, RowReport, RowStatus,
+};
+use csv::{ReaderBuilder, StringRecord};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use rusqlite::{params, Connection, Transaction, TransactionBehavior};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use utoipa::ToSchema;
+
+/// Record built from one or more rows of an import file, or why these rows are invalid
+pub struct ParsedRecord<T> {
+    pub lines: Vec<usize>,
+    pub record: AppResult<T>,
+}
+
+/// How rows with the ID of an existing record are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    // Reject them as duplicates
+    #[default]
+    Insert,
+    // Update existing records, reject rows without one
+    Update,
+    // Update existing records, insert the others
+    Upsert,
+    // Leave existing records as they are
+    SkipExisting,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    // Validate every row without storing anything
+    pub dry_run: bool,
+    // Store nothing unless every row can be imported
+    pub all_or_nothing: bool,
+}
+
+/// A row of an import file with its line number, or why it couldn't be read
+pub type ImportRow<R> = (usize, Result<R, String>);
+
+/// How an import file is written, options left empty are detected from the file
+#[derive(Debug, Default)]
+pub struct CsvDialect {
+    pub delimiter: Option<u8>,
+    pub quote: Option<u8>,
+    pub decimal: Option<u8>,
+    pub encoding: Option<&'static Encoding>,
+    // Column headers of the file mapped onto the fields they hold
+    pub mapping: HashMap<String, String>,
+}
+
+impl CsvDialect {
+    /// Builds a dialect from the import parameters, the mapping is a JSON object
+    pub fn parse(
+        delimiter: Option<char>,
+        quote: Option<char>,
+        decimal: Option<char>,
+        encoding: Option<&str>,
+        mapping: Option<&str>,
+    ) -> AppResult<Self> {
+        let byte = |name: &str, c: Option<char>| {
+            c.map(|c| {
+                u8::try_from(c).ok().filter(u8::is_ascii).ok_or_else(|| {
+                    AppError::Validation(format!("{} must be an ASCII character", name))
+                })
+            })
+            .transpose()
+        };
+
+        let decimal = byte("Decimal separator", decimal)?;
+        if decimal.is_some_and(|d| d != b'.' && d != b',') {
+            return Err(AppError::Validation(
+                "Decimal separator must be '.' or ','".into(),
+            ));
+        }
+
+        let encoding = encoding
+            .map(|label| {
+                Encoding::for_label(label.trim().as_bytes())
+                    .ok_or_else(|| AppError::Validation(format!("Unknown encoding '{}'", label)))
+            })
+            .transpose()?;
+
+        let mapping = match mapping {
+            Some(mapping) => serde_json::from_str(mapping).map_err(|e| {
+                AppError::Validation(format!(
+                    "Mapping must be a JSON object of header to field: {}",
+                    e
+                ))
+            })?,
+            None => HashMap::new(),
+        };
+
+        Ok(CsvDialect {
+            delimiter: byte("Delimiter", delimiter)?,
+            quote: byte("Quote", quote)?,
+            decimal,
+            encoding,
+            mapping,
+        })
+    }
+}
+
+/// Reads all rows of a CSV file with a header line.
+/// Rows that can't be read are kept as errors, so they show up in the import report.
+pub fn read_csv<T: CsvRecord>(
+    data: &[u8],
+    dialect: &CsvDialect,
+) -> AppResult<Vec<ImportRow<T::Row>>> {
+    let text = decode(data, dialect.encoding);
+    let delimiter = dialect.delimiter.unwrap_or_else(|| detect_delimiter(&text));
+
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .delimiter(delimiter)
+        .quote(dialect.quote.unwrap_or(b'"'))
+        .from_reader(text.as_bytes());
+    let headers = map_headers(
+        rdr.headers()
+            .map_err(|e| AppError::Validation(format!("Failed to read CSV header: {}", e)))?,
+        &dialect.mapping,
+    )?;
+
+    let records: Vec<(usize, csv::Result<StringRecord>)> = rdr
+        .records()
+        .enumerate()
+        .map(|(index, result)| {
+            let position = match &result {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            };
+            // Line 1 is the header
+            (position.map_or(index + 2, |p| p.line() as usize), result)
+        })
+        .collect();
+
+    let decimal = dialect
+        .decimal
+        .unwrap_or_else(|| detect_decimal(delimiter, &numeric_columns::<T>(&headers), &records));
+
+    Ok(deserialize_rows::<T>(&headers, records, decimal))
+}
+
+/// Deserializes rows by the field names in the header, numeric fields with a decimal
+/// comma are normalized first. Shared by all file formats, so they are validated alike.
+pub fn deserialize_rows<T: CsvRecord>(
+    headers: &StringRecord,
+    records: Vec<(usize, csv::Result<StringRecord>)>,
+    decimal: u8,
+) -> Vec<ImportRow<T::Row>> {
+    let numeric = numeric_columns::<T>(headers);
+
+    records
+        .into_iter()
+        .map(|(line, result)| {
+            let row = result.map_err(|e| e.to_string()).and_then(|record| {
+                let record = if decimal == b',' {
+                    normalize_numbers(&record, headers, &numeric)?
+                } else {
+                    record
+                };
+                record.deserialize(Some(headers)).map_err(|e| e.to_string())
+            });
+            (line, row)
+        })
+        .collect()
+}
+
+// Deserializer that only records the field names of the struct it is asked for
+struct FieldNames(&'static [&'static str]);
+
+impl<'de> Deserializer<'de> for &mut FieldNames {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("expected a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0 = fields;
+        Err(de::Error::custom("field names recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+/// Columns an import reads for a row type, in the order of its fields.
+/// Fields that are only written, like deleted_at, are not included.
+pub fn row_fields<R: DeserializeOwned>() -> &'static [&'static str] {
+    let mut names = FieldNames(&[]);
+    // Always fails, the names are recorded before
+    let _ = R::deserialize(&mut names);
+    names.0
+}
+
+// Whether each column of the header holds one of the numeric fields of the row type
+fn numeric_columns<T: CsvRecord>(headers: &StringRecord) -> Vec<bool> {
+    headers
+        .iter()
+        .map(|header| T::numeric_fields().contains(&header))
+        .collect()
+}
+
+// Files that aren't valid UTF-8 are most likely legacy Excel exports
+fn decode(data: &[u8], encoding: Option<&'static Encoding>) -> String {
+    let encoding = encoding.unwrap_or(match std::str::from_utf8(data) {
+        Ok(_) => UTF_8,
+        Err(_) => WINDOWS_1252,
+    });
+
+    // Also drops the byte order mark Excel puts in front of UTF-8 files
+    let (text, _, _) = encoding.decode(data);
+    text.into_owned()
+}
+
+// The candidate found most often in the header line, ',' if there is none
+fn detect_delimiter(text: &str) -> u8 {
+    let header = text.lines().next().unwrap_or_default();
+
+    let mut best = (b',', 0);
+    for candidate in [b',', b';', b'\t', b'|'] {
+        let count = header.bytes().filter(|&b| b == candidate).count();
+        if count > best.1 {
+            best = (candidate, count);
+        }
+    }
+    best.0
+}
+
+// Decimal commas only occur in files that don't use ',' as the delimiter
+fn detect_decimal(
+    delimiter: u8,
+    numeric: &[bool],
+    records: &[(usize, csv::Result<StringRecord>)],
+) -> u8 {
+    let comma_number = |field: &str| {
+        field.split_once(',').is_some_and(|(whole, fraction)| {
+            let whole = whole.trim_start_matches(['-', '+']);
+            !whole.is_empty()
+                && whole.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+                && !fraction.is_empty()
+                && fraction.bytes().all(|b| b.is_ascii_digit())
+        })
+    };
+
+    let found = delimiter != b','
+        && records
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().ok())
+            .any(|record| {
+                record
+                    .iter()
+                    .zip(numeric)
+                    .any(|(field, &numeric)| numeric && comma_number(field))
+            });
+
+    if found {
+        b','
+    } else {
+        b'.'
+    }
+}
+
+// Rewrites the numeric fields of a row with a decimal comma, text fields like dates
+// are left as they are
+fn normalize_numbers(
+    record: &StringRecord,
+    headers: &StringRecord,
+    numeric: &[bool],
+) -> Result<StringRecord, String> {
+    record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if !numeric.get(index).copied().unwrap_or(false) {
+                return Ok(field.to_string());
+            }
+            normalize_decimal(field).ok_or_else(|| {
+                format!(
+                    "Ambiguous number '{}' in field {}, expected a decimal comma \
+                     and dots only between groups of three digits",
+                    field,
+                    headers.get(index).unwrap_or_default()
+                )
+            })
+        })
+        .collect()
+}
+
+// Turns numbers like 1.234,5 into 1234.5. Dots are only taken as thousands separators
+// between groups of three digits, anything else like 1.5 is rejected instead of guessed.
+fn normalize_decimal(field: &str) -> Option<String> {
+    if !field.contains(['.', ',']) {
+        return Some(field.to_string());
+    }
+
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    let unsigned = field.strip_prefix(['-', '+']).unwrap_or(field);
+    let sign = &field[..field.len() - unsigned.len()];
+    let (whole, fraction) = match unsigned.split_once(',') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (unsigned, None),
+    };
+
+    let mut groups = whole.split('.');
+    let first = groups.next().unwrap_or_default();
+    let mut rest = groups.peekable();
+    let grouped = rest.peek().is_some();
+    if !digits(first)
+        || (grouped && first.len() > 3)
+        || !rest.all(|group| group.len() == 3 && digits(group))
+        || fraction.is_some_and(|fraction| !digits(fraction))
+    {
+        return None;
+    }
+
+    let mut number = format!("{}{}", sign, whole.replace('.', ""));
+    if let Some(fraction) = fraction {
+        number.push('.');
+        number.push_str(fraction);
+    }
+    Some(number)
+}
+
+/// Renames the headers found in the mapping to the fields they hold
+pub fn map_headers(
+    headers: &StringRecord,
+    mapping: &HashMap<String, String>,
+) -> AppResult<StringRecord> {
+    if let Some(missing) = mapping
+        .keys()
+        .find(|header| !headers.iter().any(|h| h == header.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "Column '{}' of the mapping is not in the file",
+            missing
+        )));
+    }
+
+    Ok(headers
+        .iter()
+        .map(|header| mapping.get(header).map_or(header, String::as_str))
+        .collect())
+}
+
+// Records stored per transaction of an import that isn't all-or-nothing
+pub const IMPORT_CHUNK_SIZE: usize = 200;
+
+/// Imports all rows, every record in its own savepoint, and reports the outcome of
+/// each row.
+///
+/// Dry runs and all-or-nothing imports run in one transaction, which holds the write
+/// lock until the whole file is through and is rolled back if nothing is to be stored.
+/// Other imports commit every `IMPORT_CHUNK_SIZE` records, so other writes get in
+/// between. Their batch is committed with the first chunk, and a cancelled or failing
+/// import keeps the chunks committed so far.
+pub fn import_records<T: Mappable + Insertable + CsvRecord + Serialize>(
+    conn: &mut Connection,
+    rows: Vec<ImportRow<T::Row>>,
+    options: ImportOptions,
+    file_name: Option<&str>,
+    actor: &str,
+    progress: &JobState,
+) -> AppResult<ImportReport> {
+    progress.set_total(rows.len());
+    let chunk_size = if options.dry_run || options.all_or_nothing {
+        usize::MAX
+    } else {
+        IMPORT_CHUNK_SIZE
+    };
+    let mut tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+    // Rolled back together with the rows unless the import is committed
+    let batch_id = if options.dry_run {
+        None
+    } else {
+        tx.execute(
+            "INSERT INTO import_batch (entity, file_name, row_count, actor, created_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now'))",
+            params![T::entity_name(), file_name, rows.len(), actor],
+        )?;
+        Some(tx.last_insert_rowid())
+    };
+
+    let mut reports = Vec::new();
+    let mut readable = Vec::new();
+    for (line, row) in rows {
+        match row {
+            Ok(row) => readable.push((line, row)),
+            Err(reason) => {
+                reports.push(RowReport::new(line, RowStatus::Invalid, None, Some(reason)))
+            }
+        }
+    }
+
+    progress.advance(reports.len());
+
+    // Rows reported up to the last commit, their outcome stands if the import stops
+    let mut committed_rows = None;
+    for (index, parsed) in T::from_csv_rows(readable, &tx)?.into_iter().enumerate() {
+        // Stopping here rolls back everything after the last commit
+        if progress.is_cancelled() {
+            reports.truncate(committed_rows.unwrap_or(reports.len()));
+            reports.sort_by_key(|report| report.line);
+            let mut report = ImportReport::new(options.dry_run, reports);
+            report.cancelled = true;
+            if committed_rows.is_some() {
+                report.committed = true;
+                report.batch_id = batch_id;
+            }
+            return Ok(report);
+        }
+
+        if index > 0 && index % chunk_size == 0 {
+            tx.commit()?;
+            committed_rows = Some(reports.len());
+            tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        }
+
+        let outcome = parsed.record.and_then(|mut record| {
+            tx.execute_batch("SAVEPOINT import_record")?;
+            match store_record(&tx, &mut record, options.mode, batch_id, actor) {
+                Ok(action) => {
+                    tx.execute_batch("RELEASE import_record")?;
+                    Ok(action)
+                }
+                Err(e) => {
+                    tx.execute_batch("ROLLBACK TO import_record; RELEASE import_record")?;
+                    Err(e)
+                }
+            }
+        });
+
+        let (status, action, reason) = match outcome {
+            Ok(Some(action)) => (RowStatus::Ok, Some(action), None),
+            Ok(None) => (RowStatus::Skipped, None, None),
+            Err(e @ AppError::DuplicateId(_)) => (RowStatus::Duplicate, None, Some(e.to_string())),
+            Err(e @ (AppError::Db(_) | AppError::Pool(_) | AppError::Internal(_))) => {
+                return Err(e)
+            }
+            Err(e) => (RowStatus::Invalid, None, Some(e.to_string())),
+        };
+
+        progress.advance(parsed.lines.len());
+        for line in parsed.lines {
+            reports.push(RowReport::new(line, status, action, reason.clone()));
+        }
+    }
+
+    reports.sort_by_key(|report| report.line);
+    let mut report = ImportReport::new(options.dry_run, reports);
+
+    if !options.dry_run && (report.passed() || !options.all_or_nothing) {
+        tx.commit()?;
+        report.committed = true;
+        report.batch_id = batch_id;
+    }
+
+    Ok(report)
+}
+
+// Inserts or updates a record depending on the mode, None if it was skipped.
+// Stored records are tagged with the batch together with the audit entry of the change.
+fn store_record<T: Mappable + Insertable + Serialize>(
+    tx: &Transaction,
+    record: &mut T,
+    mode: ImportMode,
+    batch_id: Option<i64>,
+    actor: &str,
+) -> AppResult<Option<RowAction>> {
+    // Records without an ID get a new one, so they never exist yet
+    let exists = record.id_value() > 0 && T::check_duplicate(tx, record.id_value());
+
+    let action = match (mode, exists) {
+        (ImportMode::Insert, _)
+        | (ImportMode::Upsert, false)
+        | (ImportMode::SkipExisting, false) => {
+            insert_record(tx, record, actor)?;
+            RowAction::Inserted
+        }
+        (ImportMode::Update, true) | (ImportMode::Upsert, true) => {
+            update_record(tx, record, actor)?;
+            RowAction::Updated
+        }
+        (ImportMode::SkipExisting, true) => return Ok(None),
+        (ImportMode::Update, false) => return Err(not_found::<T>(record.id_value())),
+    };
+
+    if let Some(batch_id) = batch_id {
+        tx.execute(
+            "INSERT INTO import_batch_record (batch_id, entity_id, action, audit_id)
+             SELECT ?1, ?2, ?3, MAX(id) FROM audit_log WHERE entity = ?4 AND entity_id = ?2",
+            params![
+                batch_id,
+                record.id_value(),
+                action.to_string(),
+                T::entity_name()
+            ],
+        )?;
+    }
+
+    Ok(Some(action))
+}
+
+/// Import batches, newest first, optionally only the one with the given ID
+pub fn fetch_import_batches(
+    conn: &Connection,
+    batch_id: Option<i64>,
+) -> AppResult<Vec<ImportBatch>> {
+    let mut stmt = conn.prepare(
+        "SELECT b.id, b.entity, b.file_name, b.row_count, b.actor, b.created_at, b.reverted_at,
+                COUNT(r.id) FILTER (WHERE r.action = 'inserted'),
+                COUNT(r.id) FILTER (WHERE r.action = 'updated')
+         FROM import_batch b
+         LEFT JOIN import_batch_record r ON r.batch_id = b.id
+         WHERE ?1 IS NULL OR b.id = ?1
+         GROUP BY b.id
+         ORDER BY b.id DESC",
+    )?;
+
+    let rows = stmt.query_map(params![batch_id], |row| {
+        Ok(ImportBatch {
+            id: row.get(0)?,
+            entity: row.get(1)?,
+            file_name: row.get(2)?,
+            row_count: row.get(3)?,
+            actor: row.get(4)?,
+            created_at: row.get(5)?,
+            reverted_at: row.get(6)?,
+            inserted: row.get(7)?,
+            updated: row.get(8)?,
+        })
+    })?;
+
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Deletes the records a batch inserted and restores the fields it updated, newest
+/// change first. Fails if a record was changed after the import, as reverting would
+/// silently undo that change as well.
+pub fn revert_import_batch(tx: &Transaction, batch_id: i64, actor: &str) -> AppResult<ImportBatch> {
+    let batch = fetch_import_batches(tx, Some(batch_id))?
+        .pop()
+        .ok_or_else(|| AppError::NotFound(format!("No import with id {} found", batch_id)))?;
+
+    if let Some(reverted_at) = batch.reverted_at {
+        return Err(AppError::Conflict(format!(
+            "Import {} was already reverted at {}",
+            batch_id, reverted_at
+        )));
+    }
+
+    match batch.entity.as_str() {
+        entity if entity == Article::entity_name() => {
+            revert_records::<Article>(tx, batch_id, actor)?
+        }
+        entity if entity == Customer::entity_name() => {
+            revert_records::<Customer>(tx, batch_id, actor)?
+        }
+        entity if entity == Order::entity_name() => revert_records::<Order>(tx, batch_id, actor)?,
+        entity => {
+            return Err(AppError::Internal(format!(
+                "Unknown entity {} of import {}",
+                entity, batch_id
+            )))
+        }
+    }
+
+    tx.execute(
+        "UPDATE import_batch SET reverted_at = datetime('now') WHERE id = ?1",
+        params![batch_id],
+    )?;
+
+    fetch_import_batches(tx, Some(batch_id))?
+        .pop()
+        .ok_or_else(|| AppError::NotFound(format!("No import with id {} found", batch_id)))
+}
+
+fn revert_records<T: Mappable + Insertable + Serialize + DeserializeOwned + Debug>(
+    tx: &Transaction,
+    batch_id: i64,
+    actor: &str,
+) -> AppResult<()> {
+    let changes = {
+        let mut stmt = tx.prepare(
+            "SELECT r.id, r.entity_id, r.action, r.audit_id, a.changes
+             FROM import_batch_record r
+             JOIN audit_log a ON a.id = r.audit_id
+             WHERE r.batch_id = ?1
+             ORDER BY r.id DESC",
+        )?;
+        let rows = stmt.query_map(params![batch_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    // Entries written by this revert, by other rows of the batch and by batches that
+    // were reverted since don't count, the latter cancel each other out
+    let last_audit_id: i64 =
+        tx.query_row("SELECT COALESCE(MAX(id), 0) FROM audit_log", [], |row| {
+            row.get(0)
+        })?;
+
+    for (record_id, entity_id, action, audit_id, changes) in changes {
+        let changed_since: bool = tx.query_row(
+            "SELECT EXISTS (
+                 SELECT 1 FROM audit_log
+                 WHERE entity = ?1 AND entity_id = ?2 AND id > ?3 AND id <= ?4
+                   AND id NOT IN (
+                       SELECT r.audit_id FROM import_batch_record r
+                       JOIN import_batch b ON b.id = r.batch_id
+                       WHERE r.batch_id = ?5 OR b.reverted_at IS NOT NULL
+                       UNION
+                       SELECT revert_audit_id FROM import_batch_record
+                       WHERE revert_audit_id IS NOT NULL
+                   )
+             )",
+            params![
+                T::entity_name(),
+                entity_id,
+                audit_id,
+                last_audit_id,
+                batch_id
+            ],
+            |row| row.get(0),
+        )?;
+        if changed_since {
+            return Err(AppError::Conflict(format!(
+                "{} {} was changed after the import, revert that change first",
+                T::entity_name(),
+                entity_id
+            )));
+        }
+
+        let action: RowAction = action
+            .parse()
+            .map_err(|_| AppError::Internal(format!("Unknown import action {}", action)))?;
+
+        match action {
+            // Deleted like any other record first, so stock and the audit log are kept
+            // right, then removed for good, so the same rows can be imported again
+            RowAction::Inserted => delete_record_by_id::<T>(
+                tx,
+                &Some(entity_id),
+                DeletePolicy::Restrict,
+                actor,
+            )
+            .and_then(|()| purge_record::<T>(tx, entity_id))
+            .map_err(|e| match e {
+                AppError::Referenced {
+                    entity,
+                    id_value,
+                    order_ids,
+                } => AppError::Conflict(format!(
+                    "{} {} is referenced by orders {}, delete them before reverting the import",
+                    entity,
+                    id_value,
+                    order_ids
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+                e => e,
+            })?,
+            RowAction::Updated => {
+                // Only the fields the import changed are set back
+                let changes: Value = serde_json::from_str(&changes)
+                    .map_err(|e| AppError::Internal(e.to_string()))?;
+                let mut record = to_snapshot(&find_record_by_id::<T>(tx, entity_id)?)?;
+                for (field, change) in changes.as_object().into_iter().flatten() {
+                    record[field] = change["before"].clone();
+                }
+
+                let record: T = serde_json::from_value(record)
+                    .map_err(|e| AppError::Internal(e.to_string()))?;
+                update_record(tx, &record, actor)?;
+            }
+        }
+
+        tx.execute(
+            "UPDATE import_batch_record
+             SET revert_audit_id = (SELECT MAX(id) FROM audit_log WHERE entity = ?1 AND entity_id = ?2)
+             WHERE id = ?3",
+            params![T::entity_name(), entity_id, record_id],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::operations::in_transaction;
+    use crate::core::types::tests::{place, stock, test_database};
+    use crate::core::types::{Job, OrderCsvRow, OrderStatus, OrderType};
+
+    fn csv_line(order_id: i32, article_id: i32, quantity: i32) -> OrderCsvRow {
+        OrderCsvRow {
+            order_id,
+            customer_id: 1,
+            date: "2024-03-01".into(),
+            order_type: OrderType::Sale,
+            status: OrderStatus::Pending,
+            article_id,
+            quantity,
+        }
+    }
+
+    #[test]
+    fn decimal_commas_are_normalized() {
+        assert_eq!(normalize_decimal("12,5").as_deref(), Some("12.5"));
+        assert_eq!(normalize_decimal("-1.234,50").as_deref(), Some("-1234.50"));
+        assert_eq!(normalize_decimal("1.000").as_deref(), Some("1000"));
+        assert_eq!(normalize_decimal("42").as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn ambiguous_numbers_are_rejected() {
+        assert_eq!(normalize_decimal("1.5"), None);
+        assert_eq!(normalize_decimal("15.01.2024"), None);
+        assert_eq!(normalize_decimal("1234.567,8"), None);
+        assert_eq!(normalize_decimal("1,2,3"), None);
+        assert_eq!(normalize_decimal("1,"), None);
+    }
+
+    #[test]
+    fn dates_are_kept_in_files_with_decimal_commas() {
+        let data = "order_id;customer_id;date;order_type;status;article_id;quantity\n\
+                    1;1;15.01.2024;Sale;Pending;1;2\n\
+                    2;1;16.01.2024;Sale;Pending;1;1,5\n\
+                    3;1;17.01.2024;Sale;Pending;1;1.000\n";
+
+        let rows = read_csv::<Order>(data.as_bytes(), &CsvDialect::default()).unwrap();
+
+        let first = rows[0].1.as_ref().unwrap();
+        assert_eq!(first.date, "15.01.2024");
+        assert_eq!(first.quantity, 2);
+
+        // A fractional quantity is not silently rounded or rewritten
+        assert_eq!(rows[1].0, 3);
+        assert!(rows[1].1.is_err());
+
+        let third = rows[2].1.as_ref().unwrap();
+        assert_eq!(third.date, "17.01.2024");
+        assert_eq!(third.quantity, 1000);
+    }
+
+    #[test]
+    fn ambiguous_numbers_fail_their_row() {
+        let data = "article_id;name;price;manufacturer;stock;category\n\
+                    1;Hammer;12,50;Acme;10;Tools\n\
+                    2;Nails;1.5;Acme;10;Tools\n";
+
+        let rows = read_csv::<Article>(data.as_bytes(), &CsvDialect::default()).unwrap();
+
+        assert_eq!(rows[0].1.as_ref().unwrap().price, 12.5);
+        let error = rows[1].1.as_ref().unwrap_err();
+        assert!(
+            error.contains("Ambiguous number '1.5' in field price"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn row_fields_are_the_imported_columns() {
+        assert_eq!(
+            row_fields::<OrderCsvRow>(),
+            [
+                "order_id",
+                "customer_id",
+                "date",
+                "order_type",
+                "status",
+                "article_id",
+                "quantity"
+            ]
+        );
+        assert!(!row_fields::<Article>().contains(&"deleted_at"));
+    }
+
+    // Articles 1 and 2 exist, 3 doesn't
+    fn import(conn: &mut Connection, mode: ImportMode) -> ImportReport {
+        let rows = [(1, 7), (3, 9), (2, 8)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, (article_id, stock))| {
+                let article = Article::new(
+                    article_id,
+                    format!("Imported {}", article_id),
+                    1.0,
+                    "Import".into(),
+                    stock,
+                    None,
+                );
+                (index + 2, Ok(article))
+            })
+            .collect();
+        let options = ImportOptions {
+            mode,
+            dry_run: false,
+            all_or_nothing: false,
+        };
+        let progress = JobState::new(Job::new(1, "test".into()));
+
+        import_records::<Article>(conn, rows, options, None, "test", &progress).unwrap()
+    }
+
+    fn names(conn: &Connection) -> Vec<(i32, String)> {
+        let mut stmt = conn
+            .prepare("SELECT article_id, name FROM article ORDER BY article_id")
+            .unwrap();
+        let names = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        names
+    }
+
+    fn statuses(report: &ImportReport) -> Vec<RowStatus> {
+        report.rows.iter().map(|row| row.status).collect()
+    }
+
+    #[test]
+    fn insert_mode_rejects_existing_records() {
+        let mut conn = test_database();
+
+        let report = import(&mut conn, ImportMode::Insert);
+
+        assert!(report.committed);
+        assert_eq!(
+            (report.inserted, report.updated, report.duplicate),
+            (1, 0, 2)
+        );
+        assert_eq!(
+            statuses(&report),
+            [RowStatus::Duplicate, RowStatus::Ok, RowStatus::Duplicate]
+        );
+        assert_eq!(
+            names(&conn),
+            [
+                (1, "Hammer".to_string()),
+                (2, "Nails".to_string()),
+                (3, "Imported 3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn update_mode_rejects_new_records() {
+        let mut conn = test_database();
+
+        let report = import(&mut conn, ImportMode::Update);
+
+        assert_eq!((report.inserted, report.updated, report.invalid), (0, 2, 1));
+        assert_eq!(
+            statuses(&report),
+            [RowStatus::Ok, RowStatus::Invalid, RowStatus::Ok]
+        );
+        assert_eq!(
+            names(&conn),
+            [(1, "Imported 1".to_string()), (2, "Imported 2".to_string())]
+        );
+    }
+
+    #[test]
+    fn upsert_mode_updates_and_inserts() {
+        let mut conn = test_database();
+
+        let report = import(&mut conn, ImportMode::Upsert);
+
+        assert_eq!((report.ok, report.inserted, report.updated), (3, 1, 2));
+        assert_eq!(
+            report.rows.iter().map(|row| row.action).collect::<Vec<_>>(),
+            [
+                Some(RowAction::Updated),
+                Some(RowAction::Inserted),
+                Some(RowAction::Updated)
+            ]
+        );
+        assert_eq!(names(&conn).len(), 3);
+        assert!(names(&conn)
+            .iter()
+            .all(|(_, name)| name.starts_with("Imported")));
+    }
+
+    #[test]
+    fn skip_existing_mode_leaves_existing_records() {
+        let mut conn = test_database();
+
+        let report = import(&mut conn, ImportMode::SkipExisting);
+
+        assert_eq!((report.inserted, report.updated, report.skipped), (1, 0, 2));
+        assert_eq!(
+            statuses(&report),
+            [RowStatus::Skipped, RowStatus::Ok, RowStatus::Skipped]
+        );
+        assert_eq!(
+            names(&conn),
+            [
+                (1, "Hammer".to_string()),
+                (2, "Nails".to_string()),
+                (3, "Imported 3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn dry_runs_and_failed_all_or_nothing_imports_store_nothing() {
+        let mut conn = test_database();
+        let progress = JobState::new(Job::new(1, "test".into()));
+        let rows = || {
+            vec![
+                (
+                    2,
+                    Ok(Article::new(3, "Saw".into(), 20.0, "Acme".into(), 1, None)),
+                ),
+                (3, Err("invalid price".to_string())),
+            ]
+        };
+
+        let options = ImportOptions {
+            mode: ImportMode::Insert,
+            dry_run: true,
+            all_or_nothing: false,
+        };
+        let report =
+            import_records::<Article>(&mut conn, rows(), options, None, "test", &progress).unwrap();
+        assert!(!report.committed);
+        assert_eq!((report.inserted, report.invalid), (1, 1));
+
+        let options = ImportOptions {
+            dry_run: false,
+            all_or_nothing: true,
+            ..options
+        };
+        let report =
+            import_records::<Article>(&mut conn, rows(), options, None, "test", &progress).unwrap();
+        assert!(!report.committed);
+        assert_eq!(report.batch_id, None);
+
+        assert_eq!(names(&conn).len(), 2);
+    }
+
+    #[test]
+    fn imports_that_are_not_all_or_nothing_commit_in_chunks() {
+        let mut conn = test_database();
+        let progress = JobState::new(Job::new(1, "test".into()));
+        let rows = (0..IMPORT_CHUNK_SIZE * 2 + 50)
+            .map(|index| {
+                let article =
+                    Article::new(0, format!("Bulk {}", index), 1.0, "Acme".into(), 1, None);
+                (index + 2, Ok(article))
+            })
+            .collect();
+        let options = ImportOptions {
+            mode: ImportMode::Insert,
+            dry_run: false,
+            all_or_nothing: false,
+        };
+
+        let report =
+            import_records::<Article>(&mut conn, rows, options, None, "test", &progress).unwrap();
+
+        assert!(report.committed && !report.cancelled);
+        assert_eq!(report.inserted, IMPORT_CHUNK_SIZE * 2 + 50);
+        assert_eq!(progress.snapshot().processed, IMPORT_CHUNK_SIZE * 2 + 50);
+        // Every chunk belongs to the same batch
+        let (batches, records): (i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(DISTINCT batch_id), COUNT(*) FROM import_batch_record",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((batches, records), (1, (IMPORT_CHUNK_SIZE * 2 + 50) as i64));
+        assert_eq!(names(&conn).len(), IMPORT_CHUNK_SIZE * 2 + 52);
+    }
+
+    fn revert(conn: &mut Connection, batch_id: Option<i64>) -> AppResult<ImportBatch> {
+        in_transaction(conn, |tx| {
+            revert_import_batch(tx, batch_id.unwrap(), "test")
+        })
+    }
+
+    #[test]
+    fn reverted_imports_can_be_imported_again() {
+        for mode in [ImportMode::Insert, ImportMode::Upsert] {
+            let mut conn = test_database();
+
+            let first = import(&mut conn, mode);
+            revert(&mut conn, first.batch_id).unwrap();
+            assert_eq!(names(&conn).len(), 2);
+            assert!(find_record_by_id::<Article>(&conn, 3).is_err());
+            let indexed: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM article_fts WHERE article_fts MATCH 'imported'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(indexed, 0);
+
+            let second = import(&mut conn, mode);
+            assert_eq!(
+                (second.inserted, second.updated),
+                (first.inserted, first.updated)
+            );
+            assert_eq!(names(&conn).len(), 3);
+
+            // The second import can be reverted just the same
+            revert(&mut conn, second.batch_id).unwrap();
+            assert_eq!(
+                names(&conn),
+                [(1, "Hammer".to_string()), (2, "Nails".to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn reverting_orders_removes_their_lines_and_gives_the_stock_back() {
+        let mut conn = test_database();
+        let rows = vec![(2, Ok(csv_line(7, 1, 2))), (3, Ok(csv_line(7, 2, 10)))];
+        let options = ImportOptions {
+            mode: ImportMode::Insert,
+            dry_run: false,
+            all_or_nothing: true,
+        };
+        let progress = JobState::new(Job::new(1, "test".into()));
+        let report =
+            import_records::<Order>(&mut conn, rows, options, None, "test", &progress).unwrap();
+        assert_eq!(stock(&conn, 1), 3);
+
+        revert(&mut conn, report.batch_id).unwrap();
+
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (5, 100));
+        let leftovers: i64 = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM orders) + (SELECT COUNT(*) FROM order_article)
+                      + (SELECT COUNT(*) FROM order_status_history)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn records_of_deleted_orders_are_not_reverted() {
+        let mut conn = test_database();
+        let report = import(&mut conn, ImportMode::Insert);
+        let order_id = place(&mut conn, OrderType::Sale, &[(3, 1)]).unwrap();
+        in_transaction(&mut conn, |tx| {
+            delete_record_by_id::<Order>(tx, &Some(order_id), DeletePolicy::Restrict, "test")
+        })
+        .unwrap();
+
+        match revert(&mut conn, report.batch_id) {
+            Err(AppError::Conflict(message)) => {
+                assert_eq!(message, "article 3 is still referenced by deleted orders")
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert!(find_record_by_id::<Article>(&conn, 3)
+            .unwrap()
+            .deleted_at
+            .is_none());
+    }
+}
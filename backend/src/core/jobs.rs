@@ -0,0 +1,249 @@
+// jobs.rs
+use crate::core::error::{AppError, AppResult};
+use crate::core::types::{ImportReport, Job, JobStatus};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+// Finished jobs kept for status requests, older ones are dropped
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// State of one job shared between its worker and status requests
+pub struct JobState {
+    job: Mutex<Job>,
+    cancelled: AtomicBool,
+}
+
+impl JobState {
+    pub fn new(job: Job) -> Self {
+        JobState {
+            job: Mutex::new(job),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Job> {
+        self.job.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn snapshot(&self) -> Job {
+        self.lock().clone()
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.lock().total = total;
+    }
+
+    // Called by the worker after it handled some rows
+    pub fn advance(&self, rows: usize) {
+        self.lock().processed += rows;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn finish(&self, result: AppResult<ImportReport>) {
+        let mut job = self.lock();
+        match result {
+            Ok(report) => {
+                job.status = if report.cancelled {
+                    JobStatus::Cancelled
+                } else {
+                    JobStatus::Completed
+                };
+                job.report = Some(report);
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Background jobs of this server process, they are not kept across restarts
+pub struct Jobs {
+    jobs: Mutex<BTreeMap<u32, Arc<JobState>>>,
+    // Imports write to the database, so they run one after another on a single
+    // worker thread, which ends once the queue is dropped
+    queue: mpsc::Sender<Task>,
+}
+
+pub type JobQueue = Arc<Jobs>;
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        let (queue, tasks) = mpsc::channel::<Task>();
+        thread::Builder::new()
+            .name("jobs".into())
+            .spawn(move || tasks.into_iter().for_each(|task| task()))
+            .expect("Failed to start the job worker");
+
+        Jobs {
+            jobs: Mutex::new(BTreeMap::new()),
+            queue,
+        }
+    }
+
+    fn jobs(&self) -> MutexGuard<'_, BTreeMap<u32, Arc<JobState>>> {
+        self.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn find(&self, id: u32) -> AppResult<Arc<JobState>> {
+        self.jobs()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("No job with id {} found", id)))
+    }
+
+    /// Queues the work for the worker thread and returns the queued job
+    pub fn submit<F>(&self, kind: String, work: F) -> Job
+    where
+        F: FnOnce(&JobState) -> AppResult<ImportReport> + Send + 'static,
+    {
+        let state = {
+            let mut jobs = self.jobs();
+            let job_id = jobs.keys().next_back().map_or(1, |id| id + 1);
+            let state = Arc::new(JobState::new(Job::new(job_id, kind)));
+            jobs.insert(job_id, state.clone());
+            prune(&mut jobs);
+            state
+        };
+
+        let worker = state.clone();
+        let task: Task = Box::new(move || {
+            if worker.is_cancelled() {
+                return;
+            }
+
+            worker.lock().status = JobStatus::Running;
+            // A panicking job must not take the worker down with it
+            let result = panic::catch_unwind(AssertUnwindSafe(|| work(&worker)))
+                .unwrap_or_else(|_| Err(AppError::Internal("Job panicked".into())));
+            worker.finish(result);
+        });
+        if self.queue.send(task).is_err() {
+            state.finish(Err(AppError::Internal("Job worker stopped".into())));
+        }
+
+        state.snapshot()
+    }
+
+    pub fn get(&self, id: u32) -> AppResult<Job> {
+        Ok(self.find(id)?.snapshot())
+    }
+
+    /// Queued jobs are cancelled right away, running ones once they notice and roll back
+    /// what they haven't committed
+    pub fn cancel(&self, id: u32) -> AppResult<Job> {
+        let state = self.find(id)?;
+        let mut job = state.lock();
+
+        match job.status {
+            JobStatus::Queued => job.status = JobStatus::Cancelled,
+            JobStatus::Running => {}
+            status => {
+                return Err(AppError::InvalidTransition {
+                    from: format!("{:?}", status),
+                    to: format!("{:?}", JobStatus::Cancelled),
+                })
+            }
+        }
+        state.cancelled.store(true, Ordering::Relaxed);
+
+        Ok(job.clone())
+    }
+}
+
+// Drops the oldest finished jobs beyond the limit
+fn prune(jobs: &mut BTreeMap<u32, Arc<JobState>>) {
+    let finished: Vec<u32> = jobs
+        .iter()
+        .filter(|(_, state)| state.lock().status.is_finished())
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(MAX_FINISHED_JOBS))
+    {
+        jobs.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_until_finished(jobs: &Jobs, id: u32) -> Job {
+        let started = Instant::now();
+        loop {
+            let job = jobs.get(id).unwrap();
+            if !matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+                return job;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "job {} hangs",
+                id
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn jobs_run_one_after_another() {
+        let jobs = Jobs::new();
+        let (release, blocked) = mpsc::channel::<()>();
+
+        let first = jobs.submit("first".into(), move |_| {
+            blocked.recv().unwrap();
+            Ok(ImportReport::new(false, Vec::new()))
+        });
+        let second = jobs.submit("second".into(), |_| {
+            Ok(ImportReport::new(false, Vec::new()))
+        });
+
+        // The second job waits for the worker, so it can still be cancelled before it runs
+        assert_eq!(jobs.get(second.job_id).unwrap().status, JobStatus::Queued);
+        jobs.cancel(second.job_id).unwrap();
+        release.send(()).unwrap();
+
+        assert_eq!(
+            wait_until_finished(&jobs, first.job_id).status,
+            JobStatus::Completed
+        );
+        let second = wait_until_finished(&jobs, second.job_id);
+        assert_eq!(second.status, JobStatus::Cancelled);
+        assert!(second.report.is_none());
+    }
+
+    #[test]
+    fn panicking_jobs_fail_without_stopping_the_worker() {
+        let jobs = Jobs::new();
+
+        let failing = jobs.submit("failing".into(), |_| panic!("broken file"));
+        let next = jobs.submit("next".into(), |_| Ok(ImportReport::new(false, Vec::new())));
+
+        assert_eq!(
+            wait_until_finished(&jobs, failing.job_id).status,
+            JobStatus::Failed
+        );
+        assert_eq!(
+            wait_until_finished(&jobs, next.job_id).status,
+            JobStatus::Completed
+        );
+    }
+}
@@ -0,0 +1,601 @@
+// migrations.rs
+use rusqlite::{ffi, Connection, Error, Result, Transaction};
+
+/// A numbered schema change, applied exactly once per database
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> Result<()>,
+}
+
+// Ordered by version, new migrations are only ever appended
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        up: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "snapshot unit price and article name on order lines",
+        up: order_line_snapshot,
+    },
+    Migration {
+        version: 3,
+        description: "full-text search index for articles and customers",
+        up: full_text_search,
+    },
+    Migration {
+        version: 4,
+        description: "soft delete for articles, customers and orders",
+        up: soft_delete,
+    },
+    Migration {
+        version: 5,
+        description: "audit log",
+        up: audit_log,
+    },
+    Migration {
+        version: 6,
+        description: "order status history",
+        up: order_status_history,
+    },
+    Migration {
+        version: 7,
+        description: "unique business IDs and order lines",
+        up: referential_integrity,
+    },
+    Migration {
+        version: 8,
+        description: "import batches",
+        up: import_batches,
+    },
+];
+
+// Version of the newest migration this build knows
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+// Latest version recorded in schema_version, 0 for a fresh database and for one
+// created before versioning, which has no schema_version table yet
+pub fn current_version(conn: &Connection) -> Result<i32> {
+    let versioned: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !versioned {
+        return Ok(0);
+    }
+
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Applies all pending migrations in order, each in its own transaction.
+/// Returns the schema version afterwards.
+pub fn run_migrations(conn: &mut Connection) -> Result<i32> {
+    // Foreign keys are checked by the migrations themselves, enforcing them while
+    // tables are changed fails on parent keys that aren't unique yet
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_pending(conn);
+    conn.pragma_update(None, "foreign_keys", true)?;
+
+    result
+}
+
+fn apply_pending(conn: &mut Connection) -> Result<i32> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version      INTEGER PRIMARY KEY,
+            description  TEXT NOT NULL,
+            applied_at   TEXT NOT NULL
+        );
+        ",
+    )?;
+
+    let applied = current_version(conn)?;
+    let mut version = applied;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > applied) {
+        let tx = conn.transaction()?;
+
+        (migration.up)(&tx)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description, applied_at)
+             VALUES (?1, ?2, datetime('now'))",
+            (migration.version, migration.description),
+        )?;
+
+        tx.commit()?;
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+fn column_exists(tx: &Transaction, table: &str, column: &str) -> Result<bool> {
+    tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        [table, column],
+        |row| row.get(0),
+    )
+}
+
+// Databases created before versioning already have these tables, hence IF NOT EXISTS
+fn initial_schema(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        -- Table for articles
+        CREATE TABLE IF NOT EXISTS article (
+            id             INTEGER PRIMARY KEY,
+            article_id     INTEGER NOT NULL,
+            name           TEXT NOT NULL,
+            price          REAL NOT NULL,
+            manufacturer   TEXT NOT NULL,
+            stock          INTEGER NOT NULL, 
+            category       TEXT
+        ); 
+
+        -- Table for customers
+        CREATE TABLE IF NOT EXISTS customer (
+            id             INTEGER PRIMARY KEY,
+            customer_id    INTEGER NOT NULL,
+            first_name     TEXT NOT NULL,
+            last_name      TEXT NOT NULL,
+            street         TEXT NOT NULL, 
+            location       TEXT NOT NULL,
+            zip_code       INTEGER NOT NULL,
+            email          TEXT NOT NULL
+        );
+
+        -- Table for orders
+        CREATE TABLE IF NOT EXISTS orders ( 
+            id             INTEGER PRIMARY KEY,
+            order_id       INTEGER NOT NULL,
+            customer_id    INTEGER NOT NULL,
+            date           TEXT NOT NULL,
+            order_type     TEXT NOT NULL,
+            status         TEXT NOT NULL, 
+            FOREIGN KEY(customer_id) REFERENCES customer(customer_id)
+        );
+
+        -- Table for order-article relationships
+        CREATE TABLE IF NOT EXISTS order_article (
+            id           INTEGER PRIMARY KEY,
+            order_id     INTEGER NOT NULL,
+            article_id   INTEGER NOT NULL,
+            quantity     INTEGER NOT NULL,
+            FOREIGN KEY (order_id) REFERENCES orders(order_id),
+            FOREIGN KEY (article_id) REFERENCES article(article_id)
+        );
+        ",
+    )
+}
+
+// Existing lines get the current article values, which is the best guess available
+fn order_line_snapshot(tx: &Transaction) -> Result<()> {
+    if !column_exists(tx, "order_article", "unit_price")? {
+        tx.execute_batch(
+            "
+            ALTER TABLE order_article ADD COLUMN unit_price REAL;
+            ALTER TABLE order_article ADD COLUMN article_name TEXT;
+            ",
+        )?;
+    }
+
+    tx.execute(
+        "UPDATE order_article
+         SET unit_price = (SELECT price FROM article WHERE article.article_id = order_article.article_id),
+             article_name = (SELECT name FROM article WHERE article.article_id = order_article.article_id)
+         WHERE unit_price IS NULL",
+        [],
+    )?;
+
+    Ok(())
+}
+
+// External content FTS5 tables, kept in sync with their base tables by triggers.
+// remove_diacritics lets "muller" find "Müller".
+fn full_text_search(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE VIRTUAL TABLE article_fts USING fts5(
+            name, manufacturer, category,
+            content = 'article', content_rowid = 'id',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER article_fts_insert AFTER INSERT ON article BEGIN
+            INSERT INTO article_fts (rowid, name, manufacturer, category)
+            VALUES (new.id, new.name, new.manufacturer, new.category);
+        END;
+
+        CREATE TRIGGER article_fts_delete AFTER DELETE ON article BEGIN
+            INSERT INTO article_fts (article_fts, rowid, name, manufacturer, category)
+            VALUES ('delete', old.id, old.name, old.manufacturer, old.category);
+        END;
+
+        CREATE TRIGGER article_fts_update
+        AFTER UPDATE OF name, manufacturer, category ON article BEGIN
+            INSERT INTO article_fts (article_fts, rowid, name, manufacturer, category)
+            VALUES ('delete', old.id, old.name, old.manufacturer, old.category);
+            INSERT INTO article_fts (rowid, name, manufacturer, category)
+            VALUES (new.id, new.name, new.manufacturer, new.category);
+        END;
+
+        CREATE VIRTUAL TABLE customer_fts USING fts5(
+            first_name, last_name, street, location, email,
+            content = 'customer', content_rowid = 'id',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER customer_fts_insert AFTER INSERT ON customer BEGIN
+            INSERT INTO customer_fts (rowid, first_name, last_name, street, location, email)
+            VALUES (new.id, new.first_name, new.last_name, new.street, new.location, new.email);
+        END;
+
+        CREATE TRIGGER customer_fts_delete AFTER DELETE ON customer BEGIN
+            INSERT INTO customer_fts (customer_fts, rowid, first_name, last_name, street, location, email)
+            VALUES ('delete', old.id, old.first_name, old.last_name, old.street, old.location, old.email);
+        END;
+
+        CREATE TRIGGER customer_fts_update
+        AFTER UPDATE OF first_name, last_name, street, location, email ON customer BEGIN
+            INSERT INTO customer_fts (customer_fts, rowid, first_name, last_name, street, location, email)
+            VALUES ('delete', old.id, old.first_name, old.last_name, old.street, old.location, old.email);
+            INSERT INTO customer_fts (rowid, first_name, last_name, street, location, email)
+            VALUES (new.id, new.first_name, new.last_name, new.street, new.location, new.email);
+        END;
+
+        -- Index the rows that existed before
+        INSERT INTO article_fts (article_fts) VALUES ('rebuild');
+        INSERT INTO customer_fts (customer_fts) VALUES ('rebuild');
+        ",
+    )
+}
+
+fn soft_delete(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        ALTER TABLE article ADD COLUMN deleted_at TEXT;
+        ALTER TABLE customer ADD COLUMN deleted_at TEXT;
+        ALTER TABLE orders ADD COLUMN deleted_at TEXT;
+        ",
+    )
+}
+
+fn audit_log(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE audit_log (
+            id           INTEGER PRIMARY KEY,
+            entity       TEXT NOT NULL,
+            entity_id    INTEGER NOT NULL,
+            action       TEXT NOT NULL,
+            actor        TEXT NOT NULL,
+            timestamp    TEXT NOT NULL,
+            changes      TEXT NOT NULL
+        );
+
+        CREATE INDEX audit_log_entity ON audit_log (entity, entity_id);
+        ",
+    )
+}
+
+// Existing orders start their history with their current status
+fn order_status_history(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE order_status_history (
+            id           INTEGER PRIMARY KEY,
+            order_id     INTEGER NOT NULL,
+            from_status  TEXT,
+            to_status    TEXT NOT NULL,
+            changed_at   TEXT NOT NULL
+        );
+
+        CREATE INDEX order_status_history_order ON order_status_history (order_id);
+
+        INSERT INTO order_status_history (order_id, from_status, to_status, changed_at)
+        SELECT order_id, NULL, status, datetime('now') FROM orders;
+        ",
+    )
+}
+
+// Queries listing rows that break the constraints added below, with a description each
+const INTEGRITY_CHECKS: &[(&str, &str)] = &[
+    (
+        "SELECT CAST(article_id AS TEXT) FROM article GROUP BY article_id HAVING COUNT(*) > 1",
+        "duplicate article_id",
+    ),
+    (
+        "SELECT CAST(customer_id AS TEXT) FROM customer GROUP BY customer_id HAVING COUNT(*) > 1",
+        "duplicate customer_id",
+    ),
+    (
+        "SELECT CAST(order_id AS TEXT) FROM orders GROUP BY order_id HAVING COUNT(*) > 1",
+        "duplicate order_id",
+    ),
+    (
+        "SELECT order_id || '/' || article_id FROM order_article
+         GROUP BY order_id, article_id HAVING COUNT(*) > 1",
+        "article listed more than once in order (order_id/article_id)",
+    ),
+    (
+        "SELECT CAST(order_id AS TEXT) FROM orders
+         WHERE customer_id NOT IN (SELECT customer_id FROM customer)",
+        "order with unknown customer (order_id)",
+    ),
+    (
+        "SELECT DISTINCT CAST(order_id AS TEXT) FROM order_article
+         WHERE order_id NOT IN (SELECT order_id FROM orders)",
+        "order lines without order (order_id)",
+    ),
+    (
+        "SELECT DISTINCT order_id || '/' || article_id FROM order_article
+         WHERE article_id NOT IN (SELECT article_id FROM article)",
+        "order line with unknown article (order_id/article_id)",
+    ),
+];
+
+// Existing violations are reported instead of being fixed silently,
+// the migration is only applied once the data has been cleaned up
+fn referential_integrity(tx: &Transaction) -> Result<()> {
+    let mut violations = Vec::new();
+
+    for (query, description) in INTEGRITY_CHECKS {
+        let mut stmt = tx.prepare(query)?;
+        let values = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+
+        if !values.is_empty() {
+            violations.push(format!("{}: {}", description, values.join(", ")));
+        }
+    }
+
+    if !violations.is_empty() {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CONSTRAINT),
+            Some(format!(
+                "existing data violates the new constraints, fix it and migrate again: {}",
+                violations.join("; ")
+            )),
+        ));
+    }
+
+    tx.execute_batch(
+        "
+        CREATE UNIQUE INDEX article_article_id ON article (article_id);
+        CREATE UNIQUE INDEX customer_customer_id ON customer (customer_id);
+        CREATE UNIQUE INDEX orders_order_id ON orders (order_id);
+        CREATE UNIQUE INDEX order_article_line ON order_article (order_id, article_id);
+        ",
+    )
+}
+
+// Every committed import run, and the records it inserted or updated together with
+// the audit entry of that change, which holds the values to revert to
+fn import_batches(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "
+        CREATE TABLE import_batch (
+            id           INTEGER PRIMARY KEY,
+            entity       TEXT NOT NULL,
+            file_name    TEXT,
+            row_count    INTEGER NOT NULL,
+            actor        TEXT NOT NULL,
+            created_at   TEXT NOT NULL,
+            reverted_at  TEXT
+        );
+
+        CREATE TABLE import_batch_record (
+            id           INTEGER PRIMARY KEY,
+            batch_id     INTEGER NOT NULL REFERENCES import_batch (id),
+            entity_id    INTEGER NOT NULL,
+            action       TEXT NOT NULL,
+            audit_id     INTEGER NOT NULL REFERENCES audit_log (id),
+            -- Audit entry of the revert, once the batch is reverted
+            revert_audit_id INTEGER REFERENCES audit_log (id)
+        );
+
+        CREATE INDEX import_batch_record_batch ON import_batch_record (batch_id);
+        ",
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Tables as created by initialize_tables before schema versioning, with some rows
+    pub fn baseline_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE article (
+                id             INTEGER PRIMARY KEY,
+                article_id     INTEGER NOT NULL,
+                name           TEXT NOT NULL,
+                price          REAL NOT NULL,
+                manufacturer   TEXT NOT NULL,
+                stock          INTEGER NOT NULL,
+                category       TEXT
+            );
+
+            CREATE TABLE customer (
+                id             INTEGER PRIMARY KEY,
+                customer_id    INTEGER NOT NULL,
+                first_name     TEXT NOT NULL,
+                last_name      TEXT NOT NULL,
+                street         TEXT NOT NULL,
+                location       TEXT NOT NULL,
+                zip_code       INTEGER NOT NULL,
+                email          TEXT NOT NULL
+            );
+
+            CREATE TABLE orders (
+                id             INTEGER PRIMARY KEY,
+                order_id       INTEGER NOT NULL,
+                customer_id    INTEGER NOT NULL,
+                date           TEXT NOT NULL,
+                order_type     TEXT NOT NULL,
+                status         TEXT NOT NULL,
+                FOREIGN KEY(customer_id) REFERENCES customer(customer_id)
+            );
+
+            CREATE TABLE order_article (
+                id           INTEGER PRIMARY KEY,
+                order_id     INTEGER NOT NULL,
+                article_id   INTEGER NOT NULL,
+                quantity     INTEGER NOT NULL,
+                FOREIGN KEY (order_id) REFERENCES orders(order_id),
+                FOREIGN KEY (article_id) REFERENCES article(article_id)
+            );
+
+            INSERT INTO article (article_id, name, price, manufacturer, stock, category)
+            VALUES (1, 'Hammer', 12.5, 'Acme', 10, 'Tools'),
+                   (2, 'Nails', 0.1, 'Acme', 500, NULL);
+
+            INSERT INTO customer (customer_id, first_name, last_name, street, location, zip_code, email)
+            VALUES (1, 'Erika', 'Müller', 'Hauptstraße 1', 'Berlin', 10115, 'erika@example.com');
+
+            INSERT INTO orders (order_id, customer_id, date, order_type, status)
+            VALUES (1, 1, '2024-01-15', 'Sale', 'Pending');
+
+            INSERT INTO order_article (order_id, article_id, quantity)
+            VALUES (1, 1, 2), (1, 2, 100);
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn order_lines(conn: &Connection) -> Vec<(i32, i32, f64, String)> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT article_id, quantity, unit_price, article_name FROM order_article
+                 ORDER BY article_id",
+            )
+            .unwrap();
+        let lines = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        lines
+    }
+
+    #[test]
+    fn baseline_database_upgrades_to_latest_version() {
+        let mut conn = baseline_database();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        assert_eq!(
+            order_lines(&conn),
+            vec![
+                (1, 2, 12.5, "Hammer".to_string()),
+                (2, 100, 0.1, "Nails".to_string()),
+            ]
+        );
+
+        // Later migrations work on the existing rows too
+        let history: i64 = conn
+            .query_row("SELECT COUNT(*) FROM order_status_history", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(history, 1);
+
+        let found: i32 = conn
+            .query_row(
+                "SELECT customer_id FROM customer
+                 WHERE id IN (SELECT rowid FROM customer_fts WHERE customer_fts MATCH 'muller')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn second_run_is_a_no_op() {
+        let mut conn = baseline_database();
+        run_migrations(&mut conn).unwrap();
+
+        let applied = |conn: &Connection| -> Vec<(i32, String)> {
+            let mut stmt = conn
+                .prepare("SELECT version, applied_at FROM schema_version ORDER BY version")
+                .unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            rows
+        };
+        let before = applied(&conn);
+
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert_eq!(applied(&conn), before);
+        assert_eq!(before.len(), MIGRATIONS.len());
+        assert_eq!(order_lines(&conn).len(), 2);
+    }
+
+    #[test]
+    fn fresh_database_gets_the_full_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn integrity_violations_are_reported_and_block_the_migration() {
+        let mut conn = baseline_database();
+        conn.execute_batch(
+            "
+            INSERT INTO article (article_id, name, price, manufacturer, stock, category)
+            VALUES (2, 'Screws', 0.2, 'Acme', 300, NULL);
+
+            INSERT INTO order_article (order_id, article_id, quantity) VALUES (1, 9, 1);
+            ",
+        )
+        .unwrap();
+
+        let message = run_migrations(&mut conn).unwrap_err().to_string();
+
+        assert!(message.contains("duplicate article_id: 2"), "{}", message);
+        assert!(
+            message.contains("order line with unknown article (order_id/article_id): 1/9"),
+            "{}",
+            message
+        );
+        assert!(!message.contains("duplicate customer_id"), "{}", message);
+        assert_eq!(current_version(&conn).unwrap(), 6);
+
+        // Once the data is cleaned up, migrating again picks up where it stopped.
+        // Without the unique index the old foreign keys can't be checked, like in the
+        // sqlite3 shell, where they are off by default.
+        conn.execute_batch(
+            "
+            PRAGMA foreign_keys = OFF;
+            DELETE FROM article WHERE name = 'Screws';
+            DELETE FROM order_article WHERE article_id = 9;
+            ",
+        )
+        .unwrap();
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+    }
+}
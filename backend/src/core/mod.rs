@@ -1,5 +1,13 @@
+pub mod audit;
+pub mod backup;
+pub mod error;
+pub mod import;
+pub mod jobs;
+pub mod migrations;
 pub mod operations;
 pub mod pdf;
+pub mod query;
 pub mod statistics;
 pub mod traits;
 pub mod types;
+pub mod xlsx;
@@ -1,28 +1,47 @@
 // operations.rs
-use rusqlite::{params, Connection, Error, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
 
-use axum::{http::StatusCode, response::Json as AxumJson};
-
-use crate::core::traits::{Insertable, Mappable};
-use crate::core::types::{Article, DbPool, OrderItem};
-use serde_json::json;
+use crate::core::audit::{record_audit, snapshot, to_snapshot, AuditAction};
+use crate::core::error::{AppError, AppResult, SQLITE_CONSTRAINT_FOREIGNKEY};
+use crate::core::traits::{Insertable, Mappable, TextSearchable};
+use crate::core::query::{column, ListQuery};
+use crate::core::types::{
+    Article, DbPool, Order, OrderItem, OrderStatus, OrderType, Page, StatusChange,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
 use r2d2::PooledConnection;
 use r2d2_sqlite::SqliteConnectionManager;
 
-pub fn establish_connection(
-    pool: &DbPool,
-) -> Result<PooledConnection<SqliteConnectionManager>, (StatusCode, AxumJson<serde_json::Value>)> {
-    pool.get().map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            AxumJson(json!({ "error": "Failed to get connection" })),
-        )
-    })
+pub fn establish_connection(pool: &DbPool) -> AppResult<PooledConnection<SqliteConnectionManager>> {
+    Ok(pool.get()?)
+}
+
+// Runs f inside a transaction, committing on success and rolling back on any error.
+// The write lock is taken up front, so concurrent writers queue instead of racing.
+pub fn in_transaction<R>(
+    conn: &mut Connection,
+    f: impl FnOnce(&Transaction) -> AppResult<R>,
+) -> AppResult<R> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
 }
 
-pub fn update_record<T: Insertable>(conn: &Connection, item: &T) -> Result<()> {
+pub fn update_record<T: Mappable + Insertable + Serialize>(
+    tx: &Transaction,
+    item: &T,
+    actor: &str,
+) -> AppResult<()> {
+    let previous = find_record_by_id::<T>(tx, item.id_value())?;
+    let before = to_snapshot(&previous)?;
+
     let table = T::table_name();
     let columns = T::columns();
     let id_column = T::id_column();
@@ -34,39 +53,136 @@ pub fn update_record<T: Insertable>(conn: &Connection, item: &T) -> Result<()> {
         .join(", ");
 
     let query = format!(
-        "UPDATE {} SET {} WHERE {} = ?1",
+        "UPDATE {} SET {} WHERE {} = ?1 AND deleted_at IS NULL",
         table, set_clause, id_column
     );
 
     let mut params = vec![item.id_value().into()];
     params.extend(item.values());
 
-    conn.execute(&query, rusqlite::params_from_iter(params))?;
-    Ok(())
+    let updated = tx.execute(&query, rusqlite::params_from_iter(params))?;
+    if updated == 0 {
+        return Err(not_found::<T>(item.id_value()));
+    }
+
+    item.post_update(&previous, tx)?;
+
+    let after = snapshot::<T>(tx, item.id_value())?;
+    record_audit::<T>(tx, item.id_value(), AuditAction::Update, actor, &before, &after)
+}
+
+/// Applies a JSON merge patch (RFC 7396) to a record and stores it like a regular update.
+/// Only columns of the type can be patched, the ID can't be changed.
+pub fn patch_record<T: Mappable + Insertable + Serialize + DeserializeOwned>(
+    tx: &Transaction,
+    id_value: i32,
+    patch: &Value,
+    actor: &str,
+) -> AppResult<T> {
+    let patch = patch
+        .as_object()
+        .ok_or_else(|| AppError::Validation("Patch has to be a JSON object".into()))?;
+
+    let mut record = snapshot::<T>(tx, id_value)?;
+    for (field, value) in patch {
+        let column = column::<T>(field)?;
+        if column == T::id_column() {
+            return Err(AppError::Validation(format!("{} can't be changed", column)));
+        }
+        T::patch_field(&mut record, column, value.clone());
+    }
+
+    let item: T = serde_json::from_value(record)
+        .map_err(|e| AppError::Validation(format!("Invalid patch: {}", e)))?;
+    update_record(tx, &item, actor)?;
+
+    find_record_by_id::<T>(tx, id_value)
 }
 
-pub fn find_record_by_id<T: Mappable + Insertable>(conn: &Connection, id_value: i32) -> Result<T> {
+pub fn not_found<T: Insertable>(id_value: i32) -> AppError {
+    AppError::NotFound(format!(
+        "No {} with {} {} found",
+        T::table_name(),
+        T::id_column(),
+        id_value
+    ))
+}
+
+pub fn find_record_by_id<T: Mappable + Insertable>(
+    conn: &Connection,
+    id_value: i32,
+) -> AppResult<T> {
     let table = T::table_name();
     let id_column = T::id_column();
     let columns = T::columns().join(",");
 
-    let query = format!("SELECT {} FROM {} WHERE {} = ?1", columns, table, id_column);
+    // Soft deleted records are found as well, e.g. the customer of an old order
+    let query = format!(
+        "SELECT {}, deleted_at FROM {} WHERE {} = ?1",
+        columns, table, id_column
+    );
 
     let mut stmt = conn.prepare(&query)?;
-    let mut iter = stmt.query_map([id_value], |row| T::from_row(row, conn))?;
+    let mut rows = stmt.query([id_value])?;
 
-    iter.next()
-        .ok_or_else(|| Error::QueryReturnedNoRows)?
-        .map_err(|err| err)
+    match rows.next()? {
+        Some(row) => T::from_row(row, conn),
+        None => Err(not_found::<T>(id_value)),
+    }
+}
+
+// IDs are bound as one JSON array and expanded with json_each,
+// so the number of IDs isn't limited by the number of SQL parameters
+fn json_ids(id_values: &[i32]) -> String {
+    serde_json::Value::from(id_values).to_string()
 }
 
-pub fn insert_record<T: Mappable + Insertable>(conn: &Connection, item: &T) -> Result<()> {
-    if T::check_duplicate(conn, item.id_value()) {
-        return Err(Error::ToSqlConversionFailure(Box::new(
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                format!("Item ID {} is already beeing used.", item.id_value()).to_string(),
-            ),
+/// Records with the given IDs by ID, loaded with a single query.
+/// Soft deleted records are included, the same as in `find_record_by_id`.
+pub fn find_records_by_ids<T: Mappable + Insertable>(
+    conn: &Connection,
+    id_values: &[i32],
+) -> AppResult<HashMap<i32, T>> {
+    let query = format!(
+        "SELECT {}, deleted_at FROM {} WHERE {} IN (SELECT value FROM json_each(?1))",
+        T::columns().join(","),
+        T::table_name(),
+        T::id_column()
+    );
+
+    let mut stmt = conn.prepare(&query)?;
+    let mut rows = stmt.query([json_ids(id_values)])?;
+
+    Ok(T::from_rows(&mut rows, conn)?
+        .into_iter()
+        .map(|item| (item.id_value(), item))
+        .collect())
+}
+
+// Next free ID of a table, only safe to use inside a write transaction
+fn next_id<T: Insertable>(tx: &Transaction) -> AppResult<i32> {
+    let query = format!(
+        "SELECT COALESCE(MAX({}), 0) + 1 FROM {}",
+        T::id_column(),
+        T::table_name()
+    );
+
+    Ok(tx.query_row(&query, [], |row| row.get(0))?)
+}
+
+/// Inserts the item and returns its ID.
+/// Items without an ID (0) get the next free ID assigned.
+pub fn insert_record<T: Mappable + Insertable + Serialize>(
+    tx: &Transaction,
+    item: &mut T,
+    actor: &str,
+) -> AppResult<i32> {
+    if item.id_value() <= 0 {
+        item.set_id_value(next_id::<T>(tx)?);
+    } else if T::check_duplicate(tx, item.id_value()) {
+        return Err(AppError::DuplicateId(format!(
+            "Item ID {} is already being used",
+            item.id_value()
         )));
     }
 
@@ -86,132 +202,858 @@ pub fn insert_record<T: Mappable + Insertable>(conn: &Connection, item: &T) -> R
 
     let values = item.values();
 
-    conn.execute(&query, rusqlite::params_from_iter(values))?;
+    tx.execute(&query, rusqlite::params_from_iter(values))?;
 
-    item.post_insert(conn)?;
+    item.post_insert(tx)?;
 
-    Ok(())
+    let after = snapshot::<T>(tx, item.id_value())?;
+    record_audit::<T>(tx, item.id_value(), AuditAction::Create, actor, &Value::Null, &after)?;
+
+    Ok(item.id_value())
 }
 
-pub fn delete_record_by_id<T: Mappable + Insertable + Debug>(
-    conn: &Connection,
+/// What happens to orders referencing a record that gets deleted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeletePolicy {
+    // Refuse to delete referenced records
+    Restrict,
+    // Delete the referencing orders as well
+    Cascade,
+}
+
+// How long a token for deleting a whole table can be used
+pub const DELETION_TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+
+struct IssuedToken {
+    table: &'static str,
+    count: i64,
+    issued_at: Instant,
+}
+
+/// Tokens a client has to send back to delete a whole table. A token is random, can be
+/// used once and only within its TTL, and only while the table still has as many
+/// records as when it was issued.
+pub struct DeletionTokens {
+    ttl: Duration,
+    issued: Mutex<HashMap<String, IssuedToken>>,
+}
+
+pub type DeletionTokenStore = Arc<DeletionTokens>;
+
+impl DeletionTokens {
+    pub fn new(ttl: Duration) -> Self {
+        DeletionTokens {
+            ttl,
+            issued: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn issued(&self) -> MutexGuard<'_, HashMap<String, IssuedToken>> {
+        self.issued.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn live_records<T: Insertable>(conn: &Connection) -> AppResult<i64> {
+        let query = format!(
+            "SELECT COUNT(*) FROM {} WHERE deleted_at IS NULL",
+            T::table_name()
+        );
+        Ok(conn.query_row(&query, [], |row| row.get(0))?)
+    }
+
+    pub fn issue<T: Insertable>(&self, conn: &Connection) -> AppResult<String> {
+        let count = Self::live_records::<T>(conn)?;
+        let token: String =
+            conn.query_row("SELECT lower(hex(randomblob(16)))", [], |row| row.get(0))?;
+
+        let mut issued = self.issued();
+        // Tokens that were never sent back would pile up otherwise
+        issued.retain(|_, token| token.issued_at.elapsed() < self.ttl);
+        issued.insert(
+            token.clone(),
+            IssuedToken {
+                table: T::table_name(),
+                count,
+                issued_at: Instant::now(),
+            },
+        );
+
+        Ok(token)
+    }
+
+    // Uses up the token, whether it is still valid or not
+    pub fn confirm<T: Insertable>(&self, conn: &Connection, token: &str) -> AppResult<bool> {
+        let Some(issued) = self.issued().remove(token) else {
+            return Ok(false);
+        };
+
+        Ok(issued.table == T::table_name()
+            && issued.issued_at.elapsed() < self.ttl
+            && issued.count == Self::live_records::<T>(conn)?)
+    }
+}
+
+/// Soft deletes the record with the given ID, or every record without an ID.
+/// Deleted records stay in the database and can be restored.
+pub fn delete_record_by_id<T: Mappable + Insertable + Serialize + Debug>(
+    tx: &Transaction,
     id: &Option<i32>,
-) -> Result<()> {
+    policy: DeletePolicy,
+    actor: &str,
+) -> AppResult<()> {
     let id_column = T::id_column();
     let table = T::table_name();
 
-    match id {
-        Some(id_value) => {
-            let query = format!("DELETE FROM {} WHERE {} = ?1", table, id_column);
-            conn.execute(&query, params![id_value])?;
+    // Every deleted record gets its own audit entry, so collect them beforehand
+    let id_values = match id {
+        Some(id_value) => vec![*id_value],
+        None => {
+            let query = format!("SELECT {} FROM {} WHERE deleted_at IS NULL", id_column, table);
+            let mut stmt = tx.prepare(&query)?;
+            let ids = stmt
+                .query_map([], |row| row.get::<_, i32>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            ids
+        }
+    };
+
+    for id_value in &id_values {
+        let order_ids = T::dependent_orders(*id_value, tx)?;
+        if order_ids.is_empty() {
+            continue;
+        }
 
-            // Post delete used for Order Type, to delete id-entry in article_order table
-            T::post_delete(Some(id_value), conn)?;
+        match policy {
+            DeletePolicy::Restrict => {
+                return Err(AppError::Referenced {
+                    entity: T::entity_name().to_string(),
+                    id_value: *id_value,
+                    order_ids,
+                })
+            }
+            DeletePolicy::Cascade => {
+                for order_id in order_ids {
+                    delete_record_by_id::<Order>(tx, &Some(order_id), DeletePolicy::Restrict, actor)?;
+                }
+            }
         }
-        None => {
-            let query = format!("DELETE FROM {}", table);
-            conn.execute(&query, params![])?;
+    }
+
+    // Pre delete used for Order Type, to restore the stock of the order's articles
+    T::pre_delete(id.as_ref(), tx)?;
+
+    let query = format!(
+        "UPDATE {} SET deleted_at = datetime('now') WHERE {} = ?1 AND deleted_at IS NULL",
+        table, id_column
+    );
 
-            // Post delete used for Order Type, to delete all-entries in article_order table
-            T::post_delete(None, conn)?;
+    for id_value in id_values {
+        let before = snapshot::<T>(tx, id_value)?;
+
+        if tx.execute(&query, params![id_value])? == 0 {
+            return Err(not_found::<T>(id_value));
         }
+
+        let after = snapshot::<T>(tx, id_value)?;
+        record_audit::<T>(tx, id_value, AuditAction::Delete, actor, &before, &after)?;
     }
+
+    T::post_delete(id.as_ref(), tx)?;
+
     Ok(())
 }
 
-pub fn fetch_all_records<T: Insertable + Mappable + Debug>(conn: &Connection) -> Result<Vec<T>> {
-    let table = T::table_name();
+pub fn restore_record<T: Mappable + Insertable + Serialize>(
+    tx: &Transaction,
+    id_value: i32,
+    actor: &str,
+) -> AppResult<()> {
+    let query = format!(
+        "UPDATE {} SET deleted_at = NULL WHERE {} = ?1 AND deleted_at IS NOT NULL",
+        T::table_name(),
+        T::id_column()
+    );
+
+    let before = snapshot::<T>(tx, id_value)?;
 
+    if tx.execute(&query, params![id_value])? == 0 {
+        return Err(AppError::NotFound(format!(
+            "No deleted {} with {} {} found",
+            T::table_name(),
+            T::id_column(),
+            id_value
+        )));
+    }
+
+    // Post restore used for Order Type, to take the stock of the order's articles again
+    T::post_restore(id_value, tx)?;
+
+    let after = snapshot::<T>(tx, id_value)?;
+    record_audit::<T>(tx, id_value, AuditAction::Restore, actor, &before, &after)
+}
+
+/// Removes a soft deleted record for good, so its ID is free again, e.g. when the import
+/// that inserted it is reverted. Records that deleted orders still refer to are kept.
+pub fn purge_record<T: Insertable>(tx: &Transaction, id_value: i32) -> AppResult<()> {
+    T::pre_purge(id_value, tx)?;
+
+    let query = format!(
+        "DELETE FROM {} WHERE {} = ?1 AND deleted_at IS NOT NULL",
+        T::table_name(),
+        T::id_column()
+    );
+
+    match tx.execute(&query, params![id_value]) {
+        Ok(0) => Err(AppError::NotFound(format!(
+            "No deleted {} with {} {} found",
+            T::table_name(),
+            T::id_column(),
+            id_value
+        ))),
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _))
+            if e.extended_code == SQLITE_CONSTRAINT_FOREIGNKEY =>
+        {
+            Err(AppError::Conflict(format!(
+                "{} {} is still referenced by deleted orders",
+                T::entity_name(),
+                id_value
+            )))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Fetches one page of records matching the filters of the query,
+/// together with the total number of matches
+pub fn fetch_records<T: Insertable + Mappable + Debug>(
+    conn: &Connection,
+    query: &ListQuery,
+) -> AppResult<Page<T>> {
+    let table = T::table_name();
     let columns = T::columns().join(",");
+    let (where_clause, values) = query.where_clause();
 
-    let query = format!("SELECT {} FROM {}", columns, table);
+    let count_query = format!("SELECT COUNT(*) FROM {} {}", table, where_clause);
+    let total: i64 = conn.query_row(
+        &count_query,
+        rusqlite::params_from_iter(&values),
+        |row| row.get(0),
+    )?;
 
-    let mut stmt = conn.prepare(&query)?;
+    // LIMIT -1 means no limit in SQLite
+    let select_query = format!(
+        "SELECT {}, deleted_at FROM {} {} {} LIMIT {} OFFSET {}",
+        columns,
+        table,
+        where_clause,
+        query.order_clause::<T>(),
+        query.limit.unwrap_or(-1),
+        query.offset
+    );
+
+    let mut stmt = conn.prepare(&select_query)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(&values))?;
 
-    let iter = stmt.query_map([], |row| T::from_row(row, conn))?;
+    Ok(Page {
+        items: T::from_rows(&mut rows, conn)?,
+        total,
+        limit: query.limit,
+        offset: query.offset,
+    })
+}
 
-    let mut item_list = Vec::new();
-    for item in iter {
-        item_list.push(item?);
+/// Full-text search, best matches first. Every word of the search text has to match,
+/// either completely or as a prefix ("bosch dri" finds "Bosch Drill").
+pub fn search_records<T: TextSearchable + Mappable>(
+    conn: &Connection,
+    text: &str,
+    limit: i64,
+) -> AppResult<Vec<T>> {
+    let table = T::table_name();
+    let fts_table = T::fts_table();
+
+    // Quote every word, so user input can't inject FTS5 query syntax
+    let match_query = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    if match_query.is_empty() {
+        return Ok(Vec::new());
     }
 
-    Ok(item_list)
+    let columns = T::columns()
+        .iter()
+        .map(|col| format!("{}.{}", table, col))
+        .collect::<Vec<String>>()
+        .join(",");
+
+    let query = format!(
+        "SELECT {columns}, {table}.deleted_at FROM {fts_table}
+         JOIN {table} ON {table}.id = {fts_table}.rowid
+         WHERE {fts_table} MATCH ?1 AND {table}.deleted_at IS NULL
+         ORDER BY {fts_table}.rank
+         LIMIT ?2"
+    );
+
+    let mut stmt = conn.prepare(&query)?;
+    let mut rows = stmt.query(params![match_query, limit])?;
+
+    T::from_rows(&mut rows, conn)
+}
+
+pub fn fetch_order_items(conn: &Connection, order_id: i32) -> AppResult<Vec<OrderItem>> {
+    Ok(fetch_order_items_by_order(conn, &[order_id])?
+        .remove(&order_id)
+        .unwrap_or_default())
 }
 
-pub fn fetch_order_items(conn: &Connection, order_id: i32) -> Result<Vec<OrderItem>> {
+/// Lines of all given orders by order ID, loaded with a single query
+pub fn fetch_order_items_by_order(
+    conn: &Connection,
+    order_ids: &[i32],
+) -> AppResult<HashMap<i32, Vec<OrderItem>>> {
     let mut stmt = conn.prepare(
         "
-        SELECT a.article_id, a.name, a.price, a.manufacturer, a.stock, a.category, oa.quantity
+        SELECT a.article_id, a.name, a.price, a.manufacturer, a.stock, a.category, oa.quantity,
+               oa.unit_price, oa.article_name, a.deleted_at, oa.order_id
         FROM article a
         JOIN order_article oa ON a.article_id = oa.article_id
-        WHERE oa.order_id = ?
+        WHERE oa.order_id IN (SELECT value FROM json_each(?1))
+        ORDER BY oa.order_id, oa.id
         ",
     )?;
 
-    let article_iter = stmt.query_map([order_id], |row| {
-        let article = Article::new(
-            row.get(0)?,
-            row.get(1)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(4)?,
-            row.get(5)?,
-        );
+    let article_iter = stmt.query_map([json_ids(order_ids)], |row| {
+        let article = Article {
+            deleted_at: row.get(9)?,
+            ..Article::new(
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            )
+        };
         let quantity: i32 = row.get(6)?;
-        let order_item = OrderItem::new(article, quantity);
-        Ok(order_item)
+        let order_item = OrderItem::new(article, quantity, row.get(7)?, row.get(8)?);
+        Ok((row.get::<_, i32>(10)?, order_item))
     })?;
 
-    article_iter.collect::<Result<Vec<_>, _>>()
+    let mut items_by_order: HashMap<i32, Vec<OrderItem>> = HashMap::new();
+    for item in article_iter {
+        let (order_id, order_item) = item?;
+        items_by_order.entry(order_id).or_default().push(order_item);
+    }
+
+    Ok(items_by_order)
 }
 
-pub fn initialize_tables(conn: &Connection) -> Result<()> {
-    conn.execute_batch(
-        "
-        -- Table for articles
-        CREATE TABLE IF NOT EXISTS article (
-            id             INTEGER PRIMARY KEY,
-            article_id     INTEGER NOT NULL,
-            name           TEXT NOT NULL,
-            price          REAL NOT NULL,
-            manufacturer   TEXT NOT NULL,
-            stock          INTEGER NOT NULL, 
-            category       TEXT
-        ); 
-
-        -- Table for customers
-        CREATE TABLE IF NOT EXISTS customer (
-            id             INTEGER PRIMARY KEY,
-            customer_id    INTEGER NOT NULL,
-            first_name     TEXT NOT NULL,
-            last_name      TEXT NOT NULL,
-            street         TEXT NOT NULL, 
-            location       TEXT NOT NULL,
-            zip_code       INTEGER NOT NULL,
-            email          TEXT NOT NULL
-        );
+// Adds delta to the stock of an article, rejecting movements below zero
+pub fn adjust_stock(conn: &Connection, article_id: i32, delta: i32) -> AppResult<()> {
+    let changed = conn.execute(
+        "UPDATE article SET stock = stock + ?1 WHERE article_id = ?2 AND stock + ?1 >= 0",
+        params![delta, article_id],
+    )?;
 
-        -- Table for orders
-        CREATE TABLE IF NOT EXISTS orders ( 
-            id             INTEGER PRIMARY KEY,
-            order_id       INTEGER NOT NULL,
-            customer_id    INTEGER NOT NULL,
-            date           TEXT NOT NULL,
-            order_type     TEXT NOT NULL,
-            status         TEXT NOT NULL, 
-            FOREIGN KEY(customer_id) REFERENCES customer(customer_id)
-        );
+    if changed == 0 {
+        let available: Option<i32> = conn
+            .query_row(
+                "SELECT stock FROM article WHERE article_id = ?1",
+                [article_id],
+                |row| row.get(0),
+            )
+            .optional()?;
 
-        -- Table for order-article relationships
-        CREATE TABLE IF NOT EXISTS order_article (
-            id           INTEGER PRIMARY KEY,
-            order_id     INTEGER NOT NULL,
-            article_id   INTEGER NOT NULL,
-            quantity     INTEGER NOT NULL,
-            FOREIGN KEY (order_id) REFERENCES orders(order_id),
-            FOREIGN KEY (article_id) REFERENCES article(article_id)
-        );
-        ",
+        // Article no longer exists, so there is no stock left to adjust
+        if let Some(available) = available {
+            return Err(AppError::InsufficientStock {
+                article_id,
+                requested: -delta,
+                available,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Reverses the stock movement caused by an order, e.g. when it gets deleted
+pub fn revert_order_stock(conn: &Connection, order_id: i32) -> AppResult<()> {
+    move_order_stock(conn, order_id, -1)
+}
+
+// Applies the stock movement of an existing order again, e.g. when it gets restored
+pub fn apply_order_stock(conn: &Connection, order_id: i32) -> AppResult<()> {
+    move_order_stock(conn, order_id, 1)
+}
+
+// Cancelled orders have already given back their stock and are skipped
+fn move_order_stock(conn: &Connection, order_id: i32, direction: i32) -> AppResult<()> {
+    let order_type: Option<String> = conn
+        .query_row(
+            "SELECT order_type FROM orders WHERE order_id = ?1 AND status != 'Cancelled'",
+            [order_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let Some(order_type) = order_type.as_deref().and_then(OrderType::from_str) else {
+        return Ok(());
+    };
+
+    let mut stmt =
+        conn.prepare("SELECT article_id, quantity FROM order_article WHERE order_id = ?1")?;
+    let lines = stmt
+        .query_map([order_id], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (article_id, quantity) in lines {
+        adjust_stock(conn, article_id, direction * order_type.stock_delta(quantity))?;
+    }
+
+    Ok(())
+}
+
+// Validates a status change of an order and records it in its history
+pub fn record_status_change(
+    tx: &Transaction,
+    order_id: i32,
+    from: Option<OrderStatus>,
+    to: OrderStatus,
+) -> AppResult<()> {
+    if let Some(from) = from {
+        if !from.can_transition_to(to) {
+            return Err(AppError::InvalidTransition {
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+    }
+
+    tx.execute(
+        "INSERT INTO order_status_history (order_id, from_status, to_status, changed_at)
+         VALUES (?1, ?2, ?3, datetime('now'))",
+        params![order_id, from.map(|s| s.to_string()), to.to_string()],
     )?;
+
     Ok(())
 }
+
+// Moves an order to a new status, going through the same checks as a regular update.
+// Setting the current status again is rejected, as it is no transition.
+pub fn change_order_status(
+    tx: &Transaction,
+    order_id: i32,
+    status: OrderStatus,
+    actor: &str,
+) -> AppResult<Order> {
+    let mut order = find_record_by_id::<Order>(tx, order_id)?;
+    if order.deleted_at.is_some() {
+        return Err(not_found::<Order>(order_id));
+    }
+    if order.status == status {
+        return Err(AppError::InvalidTransition {
+            from: order.status.to_string(),
+            to: status.to_string(),
+        });
+    }
+
+    order.status = status;
+    update_record(tx, &order, actor)?;
+
+    find_record_by_id::<Order>(tx, order_id)
+}
+
+/// Status history of an order, oldest first
+pub fn fetch_status_history(conn: &Connection, order_id: i32) -> AppResult<Vec<StatusChange>> {
+    let mut stmt = conn.prepare(
+        "SELECT order_id, from_status, to_status, changed_at
+         FROM order_status_history
+         WHERE order_id = ?1
+         ORDER BY id ASC",
+    )?;
+
+    let rows = stmt.query_map([order_id], |row| {
+        Ok(StatusChange::new(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::tests::{new_order, place, stock, test_database};
+    use crate::core::types::OrderItem;
+    use serde_json::json;
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn errors_roll_back_the_whole_transaction() {
+        let mut conn = test_database();
+
+        let result: AppResult<()> = in_transaction(&mut conn, |tx| {
+            tx.execute("UPDATE article SET stock = 0 WHERE article_id = 1", [])?;
+            Err(AppError::Internal("failed".into()))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(stock(&conn, 1), 5);
+    }
+
+    #[test]
+    fn failing_order_lines_leave_no_partial_order() {
+        let mut conn = test_database();
+        let mut order = new_order(&conn, OrderType::Sale, &[(1, 2)]);
+        let mut missing = order.items[0].article.clone();
+        missing.article_id = 99;
+        order.items.push(OrderItem::new(missing, 1, None, None));
+
+        let result = in_transaction(&mut conn, |tx| insert_record(tx, &mut order, "test"));
+
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert_eq!(count(&conn, "orders"), 0);
+        assert_eq!(count(&conn, "order_article"), 0);
+        assert_eq!(count(&conn, "order_status_history"), 0);
+        assert_eq!(count(&conn, "audit_log"), 0);
+        assert_eq!(stock(&conn, 1), 5);
+    }
+
+    #[test]
+    fn failing_deletes_keep_every_record() {
+        let mut conn = test_database();
+        for (order_type, quantity) in [
+            (OrderType::Sale, 2),
+            (OrderType::Return, 3),
+            (OrderType::Sale, 6),
+        ] {
+            let mut order = new_order(&conn, order_type, &[(1, quantity)]);
+            in_transaction(&mut conn, |tx| insert_record(tx, &mut order, "test")).unwrap();
+        }
+        assert_eq!(stock(&conn, 1), 0);
+
+        // The first sale gives back its stock, then taking back the return fails
+        let result = in_transaction(&mut conn, |tx| {
+            delete_record_by_id::<Order>(tx, &None, DeletePolicy::Restrict, "test")
+        });
+
+        assert!(matches!(result, Err(AppError::InsufficientStock { .. })));
+        assert_eq!(stock(&conn, 1), 0);
+        let live: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM orders WHERE deleted_at IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(live, 3);
+    }
+
+    fn new_article(article_id: i32) -> Article {
+        Article::new(
+            article_id,
+            "Saw".to_string(),
+            20.0,
+            "Acme".to_string(),
+            3,
+            None,
+        )
+    }
+
+    #[test]
+    fn records_without_an_id_get_the_next_free_one() {
+        let mut conn = test_database();
+
+        let mut article = new_article(0);
+        let id_value =
+            in_transaction(&mut conn, |tx| insert_record(tx, &mut article, "test")).unwrap();
+
+        assert_eq!(id_value, 3);
+        assert_eq!(article.article_id, 3);
+        assert_eq!(find_record_by_id::<Article>(&conn, 3).unwrap().name, "Saw");
+    }
+
+    #[test]
+    fn ids_of_deleted_records_are_not_reused() {
+        let mut conn = test_database();
+        in_transaction(&mut conn, |tx| {
+            delete_record_by_id::<Article>(tx, &Some(2), DeletePolicy::Restrict, "test")
+        })
+        .unwrap();
+
+        let id_value = in_transaction(&mut conn, |tx| {
+            insert_record(tx, &mut new_article(0), "test")
+        })
+        .unwrap();
+        assert_eq!(id_value, 3);
+    }
+
+    #[test]
+    fn taken_ids_are_rejected() {
+        let mut conn = test_database();
+
+        let result = in_transaction(&mut conn, |tx| {
+            insert_record(tx, &mut new_article(1), "test")
+        });
+        assert!(matches!(result, Err(AppError::DuplicateId(_))));
+
+        let id_value = in_transaction(&mut conn, |tx| {
+            insert_record(tx, &mut new_article(10), "test")
+        })
+        .unwrap();
+        assert_eq!(id_value, 10);
+    }
+
+    fn history(conn: &Connection, order_id: i32) -> Vec<(Option<String>, String)> {
+        fetch_status_history(conn, order_id)
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.from_status, change.to_status))
+            .collect()
+    }
+
+    fn change_status(
+        conn: &mut Connection,
+        order_id: i32,
+        status: OrderStatus,
+    ) -> AppResult<Order> {
+        in_transaction(conn, |tx| change_order_status(tx, order_id, status, "test"))
+    }
+
+    #[test]
+    fn transition_table() {
+        use OrderStatus::*;
+        let all = [Pending, Completed, Shipped, Delivered, Cancelled];
+        let allowed = [
+            (Pending, Completed),
+            (Pending, Cancelled),
+            (Completed, Shipped),
+            (Completed, Cancelled),
+            (Shipped, Delivered),
+        ];
+
+        for from in all {
+            for to in all {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn allowed_transitions_are_recorded() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 1)]).unwrap();
+
+        for status in [
+            OrderStatus::Completed,
+            OrderStatus::Shipped,
+            OrderStatus::Delivered,
+        ] {
+            assert_eq!(
+                change_status(&mut conn, order_id, status).unwrap().status,
+                status
+            );
+        }
+
+        assert_eq!(
+            history(&conn, order_id),
+            vec![
+                (None, "Pending".to_string()),
+                (Some("Pending".to_string()), "Completed".to_string()),
+                (Some("Completed".to_string()), "Shipped".to_string()),
+                (Some("Shipped".to_string()), "Delivered".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn illegal_transitions_are_rejected() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 1)]).unwrap();
+
+        // Skipping a status and setting the current one again are both rejected
+        for status in [OrderStatus::Shipped, OrderStatus::Pending] {
+            assert!(matches!(
+                change_status(&mut conn, order_id, status),
+                Err(AppError::InvalidTransition { .. })
+            ));
+        }
+        assert_eq!(history(&conn, order_id).len(), 1);
+
+        change_status(&mut conn, order_id, OrderStatus::Cancelled).unwrap();
+        assert!(matches!(
+            change_status(&mut conn, order_id, OrderStatus::Completed),
+            Err(AppError::InvalidTransition { .. })
+        ));
+    }
+
+    #[test]
+    fn cancelling_gives_the_stock_back() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 4), (2, 10)]).unwrap();
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (1, 90));
+
+        change_status(&mut conn, order_id, OrderStatus::Cancelled).unwrap();
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (5, 100));
+
+        // Deleting a cancelled order doesn't give the stock back twice
+        in_transaction(&mut conn, |tx| {
+            delete_record_by_id::<Order>(tx, &Some(order_id), DeletePolicy::Restrict, "test")
+        })
+        .unwrap();
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (5, 100));
+    }
+
+    fn patch<T: Mappable + Insertable + Serialize + DeserializeOwned>(
+        conn: &mut Connection,
+        id_value: i32,
+        patch: Value,
+    ) -> AppResult<T> {
+        in_transaction(conn, |tx| patch_record::<T>(tx, id_value, &patch, "test"))
+    }
+
+    #[test]
+    fn patches_change_only_the_given_fields() {
+        let mut conn = test_database();
+
+        let article: Article = patch(&mut conn, 1, json!({ "stock": 42 })).unwrap();
+
+        assert_eq!(article.stock, 42);
+        assert_eq!(article.name, "Hammer");
+        assert_eq!(article.price, 12.5);
+        assert_eq!(article.category.as_deref(), Some("Tools"));
+    }
+
+    #[test]
+    fn patches_with_unknown_fields_are_rejected() {
+        let mut conn = test_database();
+
+        let result = patch::<Article>(&mut conn, 1, json!({ "stock": 42, "colour": "red" }));
+
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert_eq!(stock(&conn, 1), 5);
+    }
+
+    #[test]
+    fn patches_cant_change_the_id() {
+        let mut conn = test_database();
+
+        let result = patch::<Article>(&mut conn, 1, json!({ "article_id": 7 }));
+
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert!(find_record_by_id::<Article>(&conn, 1).is_ok());
+        assert!(matches!(
+            find_record_by_id::<Article>(&conn, 7),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn patches_have_to_be_objects() {
+        let mut conn = test_database();
+
+        let result = patch::<Article>(&mut conn, 1, json!([{ "stock": 42 }]));
+
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn patches_with_invalid_values_are_rejected() {
+        let mut conn = test_database();
+
+        let result = patch::<Article>(&mut conn, 1, json!({ "stock": "many" }));
+
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert_eq!(stock(&conn, 1), 5);
+    }
+
+    #[test]
+    fn patching_the_customer_of_an_order() {
+        let mut conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO customer (customer_id, first_name, last_name, street, location, zip_code, email)
+             VALUES (2, 'Max', 'Mustermann', 'Ring 2', 'Hamburg', 20095, 'max@example.com')",
+        )
+        .unwrap();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 2)]).unwrap();
+
+        let order: Order = patch(&mut conn, order_id, json!({ "customer_id": 2 })).unwrap();
+
+        assert_eq!(order.customer.customer_id, 2);
+        assert_eq!(order.items.len(), 1);
+        assert_eq!(stock(&conn, 1), 3);
+    }
+
+    #[test]
+    fn deletion_tokens_are_random() {
+        let conn = test_database();
+        let tokens = DeletionTokens::new(DELETION_TOKEN_TTL);
+
+        let first = tokens.issue::<Article>(&conn).unwrap();
+        let second = tokens.issue::<Article>(&conn).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 32);
+    }
+
+    #[test]
+    fn deletion_tokens_can_be_used_once() {
+        let conn = test_database();
+        let tokens = DeletionTokens::new(DELETION_TOKEN_TTL);
+
+        let token = tokens.issue::<Article>(&conn).unwrap();
+
+        assert!(tokens.confirm::<Article>(&conn, &token).unwrap());
+        assert!(!tokens.confirm::<Article>(&conn, &token).unwrap());
+        assert!(!tokens.confirm::<Article>(&conn, "made-up").unwrap());
+    }
+
+    #[test]
+    fn deletion_tokens_only_apply_to_their_table() {
+        let conn = test_database();
+        let tokens = DeletionTokens::new(DELETION_TOKEN_TTL);
+
+        let token = tokens.issue::<Article>(&conn).unwrap();
+
+        assert!(!tokens.confirm::<Order>(&conn, &token).unwrap());
+    }
+
+    #[test]
+    fn deletion_tokens_expire_when_records_change() {
+        let mut conn = test_database();
+        let tokens = DeletionTokens::new(DELETION_TOKEN_TTL);
+
+        let token = tokens.issue::<Article>(&conn).unwrap();
+        in_transaction(&mut conn, |tx| {
+            insert_record(tx, &mut new_article(0), "test")
+        })
+        .unwrap();
+
+        assert!(!tokens.confirm::<Article>(&conn, &token).unwrap());
+    }
+
+    #[test]
+    fn deletion_tokens_expire_after_their_ttl() {
+        let conn = test_database();
+        let tokens = DeletionTokens::new(Duration::ZERO);
+
+        let token = tokens.issue::<Article>(&conn).unwrap();
+
+        assert!(!tokens.confirm::<Article>(&conn, &token).unwrap());
+    }
+}
@@ -5,23 +5,17 @@ use axum::{
 };
 
 use super::html::get_html;
+use crate::core::error::{AppError, AppResult};
 use crate::core::types::Order;
 
 use headless_chrome::Browser;
 use urlencoding::encode;
 
-pub async fn fetch_pdf(Json(order): Json<Order>) -> Response {
-    let pdf_data: Vec<u8> = match generate_pdf(&order) {
-        Ok(data) => data,
-        Err(e) => {
-            eprintln!("Error while generating pdf: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Error while generating pdf",
-            )
-                .into_response();
-        }
-    };
+pub async fn fetch_pdf(Json(order): Json<Order>) -> AppResult<Response> {
+    let pdf_data: Vec<u8> = generate_pdf(&order).map_err(|e| {
+        eprintln!("Error while generating pdf: {}", e);
+        AppError::Internal("Error while generating pdf".into())
+    })?;
 
     let mut headers = HeaderMap::new();
     headers.insert("Content-Type", HeaderValue::from_static("application/pdf"));
@@ -30,16 +24,16 @@ pub async fn fetch_pdf(Json(order): Json<Order>) -> Response {
         HeaderValue::from_static("inline; filename=\"auftrag.pdf\""),
     );
 
-    (StatusCode::OK, headers, pdf_data).into_response()
+    Ok((StatusCode::OK, headers, pdf_data).into_response())
 }
 
 fn generate_pdf(order: &Order) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let browser = Browser::default()?;
     let tab = browser.new_tab()?;
 
-    let html = get_html(&order)?;
+    let html = get_html(order)?;
 
-    let data_url = format!("data:text/html,{}", encode(&*html));
+    let data_url = format!("data:text/html,{}", encode(&html));
     tab.navigate_to(&data_url)?;
     tab.wait_until_navigated()?;
 
@@ -12,7 +12,7 @@ pub fn get_html(order: &Order) -> Result<String, Box<dyn std::error::Error>> {
     let mut items_html = String::new();
     let mut total = 0.0;
     for item in items {
-        let subtotal = item.article.price * (item.quantity as f64);
+        let subtotal = item.price() * (item.quantity as f64);
         total += subtotal;
         items_html.push_str(&format!(
             r#"<tr>
@@ -23,13 +23,15 @@ pub fn get_html(order: &Order) -> Result<String, Box<dyn std::error::Error>> {
                 <td>{subtotal:.2} €</td>
             </tr>"#,
             article_id = item.article.article_id,
-            name = item.article.name,
+            name = item.name(),
             quantity = item.quantity,
-            price = item.article.price,
+            price = item.price(),
             subtotal = subtotal
         ));
     }
 
+    let customer_name = format!("{} {}", customer.first_name, customer.last_name);
+
     let html = format!(
         r#"<!DOCTYPE html>
 <html lang="de">
@@ -92,7 +94,7 @@ pub fn get_html(order: &Order) -> Result<String, Box<dyn std::error::Error>> {
 </body>
 </html>
 "#,
-        customer_name = format!("{} {}", customer.first_name, customer.last_name),
+        customer_name = customer_name,
         customer_address = customer.street,
         customer_email = customer.email,
         order_id = order_id,
@@ -0,0 +1,170 @@
+// query.rs
+use crate::core::error::{AppError, AppResult};
+use crate::core::traits::Insertable;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    Min,
+    Max,
+}
+
+impl FilterOp {
+    fn sql(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Min => ">=",
+            FilterOp::Max => "<=",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Filter {
+    pub column: &'static str,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Pagination, sorting and filters of a list request.
+/// Column names are always taken from `Insertable::columns()`, never from the request.
+#[derive(Debug, Default)]
+pub struct ListQuery {
+    pub limit: Option<i64>,
+    pub offset: i64,
+    pub include_deleted: bool,
+    pub sort: Option<(&'static str, SortOrder)>,
+    pub filters: Vec<Filter>,
+}
+
+// Query parameters that are not column filters
+const RESERVED_PARAMS: &[&str] = &["limit", "offset", "sort", "include_deleted"];
+
+impl ListQuery {
+    /// Parses `limit`, `offset`, `sort=column:asc|desc`, `include_deleted` and column filters
+    /// (`column=value`, `column_min=value`, `column_max=value`).
+    pub fn parse<T: Insertable>(params: &HashMap<String, String>) -> AppResult<Self> {
+        let mut query = ListQuery {
+            limit: params
+                .get("limit")
+                .map(|v| parse_non_negative("limit", v))
+                .transpose()?,
+            offset: params
+                .get("offset")
+                .map(|v| parse_non_negative("offset", v))
+                .transpose()?
+                .unwrap_or(0),
+            include_deleted: params
+                .get("include_deleted")
+                .map(|v| parse_bool("include_deleted", v))
+                .transpose()?
+                .unwrap_or(false),
+            ..Default::default()
+        };
+
+        if let Some(sort) = params.get("sort") {
+            let (field, order) = sort.split_once(':').unwrap_or((sort, "asc"));
+            let order = match order {
+                "asc" => SortOrder::Asc,
+                "desc" => SortOrder::Desc,
+                _ => {
+                    return Err(AppError::Validation(format!(
+                        "Invalid sort order '{}', expected asc or desc",
+                        order
+                    )))
+                }
+            };
+            query.sort = Some((column::<T>(field)?, order));
+        }
+
+        for (key, value) in params {
+            if RESERVED_PARAMS.contains(&key.as_str()) {
+                continue;
+            }
+
+            let (field, op) = if let Some(field) = key.strip_suffix("_min") {
+                (field, FilterOp::Min)
+            } else if let Some(field) = key.strip_suffix("_max") {
+                (field, FilterOp::Max)
+            } else {
+                (key.as_str(), FilterOp::Eq)
+            };
+
+            query.filters.push(Filter {
+                column: column::<T>(field)?,
+                op,
+                value: value.clone(),
+            });
+        }
+
+        Ok(query)
+    }
+
+    // WHERE clause with ?-placeholders and the values to bind, empty without conditions
+    pub fn where_clause(&self) -> (String, Vec<String>) {
+        let mut conditions = self
+            .filters
+            .iter()
+            .map(|f| format!("{} {} ?", f.column, f.op.sql()))
+            .collect::<Vec<String>>();
+        let values = self.filters.iter().map(|f| f.value.clone()).collect();
+
+        if !self.include_deleted {
+            conditions.push("deleted_at IS NULL".to_string());
+        }
+
+        if conditions.is_empty() {
+            return (String::new(), values);
+        }
+
+        (format!("WHERE {}", conditions.join(" AND ")), values)
+    }
+
+    // The ID column breaks ties, so pages are stable
+    pub fn order_clause<T: Insertable>(&self) -> String {
+        match self.sort {
+            Some((column, SortOrder::Asc)) => {
+                format!("ORDER BY {} ASC, {} ASC", column, T::id_column())
+            }
+            Some((column, SortOrder::Desc)) => {
+                format!("ORDER BY {} DESC, {} ASC", column, T::id_column())
+            }
+            None => format!("ORDER BY {} ASC", T::id_column()),
+        }
+    }
+}
+
+// Resolves a field name from the request to the matching whitelisted column
+pub fn column<T: Insertable>(field: &str) -> AppResult<&'static str> {
+    T::columns()
+        .into_iter()
+        .find(|column| *column == field)
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "Unknown field '{}' for {}",
+                field,
+                T::table_name()
+            ))
+        })
+}
+
+fn parse_bool(name: &str, value: &str) -> AppResult<bool> {
+    value
+        .parse::<bool>()
+        .map_err(|_| AppError::Validation(format!("{} must be true or false", name)))
+}
+
+fn parse_non_negative(name: &str, value: &str) -> AppResult<i64> {
+    value
+        .parse::<i64>()
+        .ok()
+        .filter(|v| *v >= 0)
+        .ok_or_else(|| AppError::Validation(format!("{} must be a non-negative integer", name)))
+}
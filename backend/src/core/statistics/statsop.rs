@@ -4,9 +4,11 @@ use std::collections::HashMap;
 
 pub fn get_ordered_quantities(conn: &Connection) -> Result<HashMap<i32, i32>> {
     let mut stmt = conn.prepare(
-        "SELECT article_id, SUM(quantity) AS anzahl_artikel
-          FROM order_article
-          GROUP BY article_id",
+        "SELECT oa.article_id, SUM(oa.quantity) AS anzahl_artikel
+          FROM order_article oa
+          JOIN orders o ON o.order_id = oa.order_id
+          WHERE o.deleted_at IS NULL AND o.status != 'Cancelled'
+          GROUP BY oa.article_id",
     )?;
 
     let mut results: HashMap<i32, i32> = HashMap::new();
@@ -15,10 +17,8 @@ pub fn get_ordered_quantities(conn: &Connection) -> Result<HashMap<i32, i32>> {
         .query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)))
         .unwrap();
 
-    for row in rows {
-        if let Ok((article_id, order_count)) = row {
-            results.insert(article_id, order_count);
-        }
+    for (article_id, order_count) in rows.flatten() {
+        results.insert(article_id, order_count);
     }
 
     Ok(results)
@@ -26,10 +26,11 @@ pub fn get_ordered_quantities(conn: &Connection) -> Result<HashMap<i32, i32>> {
 
 pub fn get_article_revenue(conn: &Connection) -> Result<HashMap<i32, f64>> {
     let mut stmt = conn.prepare(
-        "SELECT a.article_id, SUM(a.price * oa.quantity) AS revenue
-         FROM article a
-         JOIN order_article oa ON a.article_id = oa.article_id
-         GROUP BY a.article_id",
+        "SELECT oa.article_id, SUM(oa.unit_price * oa.quantity) AS revenue
+         FROM order_article oa
+         JOIN orders o ON o.order_id = oa.order_id
+         WHERE o.deleted_at IS NULL AND o.status != 'Cancelled'
+         GROUP BY oa.article_id",
     )?;
 
     let mut revenue_map = HashMap::new();
@@ -49,10 +50,10 @@ pub fn get_article_revenue(conn: &Connection) -> Result<HashMap<i32, f64>> {
 
 pub fn get_total_prices(conn: &Connection) -> Result<HashMap<i32, f64>> {
     let mut stmt = conn.prepare(
-        "SELECT o.order_id, SUM(a.price * oa.quantity) AS total_price
+        "SELECT o.order_id, SUM(oa.unit_price * oa.quantity) AS total_price
          FROM orders o
          JOIN order_article oa ON o.order_id = oa.order_id
-         JOIN article a ON a.article_id = oa.article_id
+         WHERE o.deleted_at IS NULL AND o.status != 'Cancelled'
          GROUP BY o.order_id",
     )?;
 
@@ -70,6 +71,7 @@ pub fn get_total_orders_customer(conn: &Connection) -> Result<HashMap<i32, i32>>
     let mut stmt = conn.prepare(
         "SELECT customer_id, COUNT(order_id) AS order_count 
          FROM orders 
+         WHERE deleted_at IS NULL AND status != 'Cancelled'
          GROUP BY customer_id",
     )?;
 
@@ -91,10 +93,10 @@ pub fn get_total_orders_customer(conn: &Connection) -> Result<HashMap<i32, i32>>
 
 pub fn get_total_revenue_customer(conn: &Connection) -> Result<HashMap<i32, f64>> {
     let mut stmt = conn.prepare(
-        "SELECT o.customer_id, COALESCE(SUM(a.price * oa.quantity), 0) AS total_revenue
+        "SELECT o.customer_id, COALESCE(SUM(oa.unit_price * oa.quantity), 0) AS total_revenue
          FROM orders o
          LEFT JOIN order_article oa ON o.order_id = oa.order_id
-         LEFT JOIN article a ON oa.article_id = a.article_id
+         WHERE o.deleted_at IS NULL AND o.status != 'Cancelled'
          GROUP BY o.customer_id",
     )?;
 
@@ -116,11 +118,10 @@ pub fn get_total_revenue_customer(conn: &Connection) -> Result<HashMap<i32, f64>
 
 pub fn get_most_bought_item_customer(conn: &Connection) -> Result<HashMap<i32, String>> {
     let mut stmt = conn.prepare(
-        "SELECT o.customer_id, a.name 
+        "SELECT o.customer_id, oa.article_name 
          FROM orders o
          JOIN order_article oa ON o.order_id = oa.order_id
-         JOIN article a ON oa.article_id = a.article_id
-         WHERE oa.quantity = (
+         WHERE o.deleted_at IS NULL AND o.status != 'Cancelled' AND oa.quantity = (
              SELECT MAX(sub_oa.quantity) 
              FROM order_article sub_oa 
              WHERE sub_oa.order_id = o.order_id
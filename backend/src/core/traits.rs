@@ -1,21 +1,61 @@
 // traits.rs
+use crate::core::error::AppResult;
+use crate::core::import::ParsedRecord;
 use rusqlite::types::ToSqlOutput;
-use rusqlite::{Connection, Result, Row};
+use rusqlite::{Connection, Row, Rows, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 
 /// Mapping of database row to a type
 pub trait Mappable {
-    fn from_row(row: &Row, conn: &Connection) -> Result<Self>
+    fn from_row(row: &Row, conn: &Connection) -> AppResult<Self>
     where
         Self: Sized;
+
+    // Maps every selected row. Types with related records override this,
+    // so those are loaded in bulk instead of with queries per row.
+    fn from_rows(rows: &mut Rows, conn: &Connection) -> AppResult<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        let mut items = Vec::new();
+        while let Some(row) = rows.next()? {
+            items.push(Self::from_row(row, conn)?);
+        }
+        Ok(items)
+    }
 }
 
 /// Allows searching for an record in the database by ID
 pub trait Searchable {
-    fn search(conn: &Connection, id_value: i32) -> Result<Self>
+    fn search(conn: &Connection, id_value: i32) -> AppResult<Self>
     where
         Self: Sized;
 }
 
+/// Conversion between records and CSV rows, where one record can span several rows.
+/// Exported rows use the same format as imported ones.
+pub trait CsvRecord: Sized {
+    type Row: DeserializeOwned + Serialize;
+
+    // Builds the records of all parsed rows with their line numbers and checks the records
+    // they reference. Invalid records are returned as errors, so every row can be reported.
+    fn from_csv_rows(
+        rows: Vec<(usize, Self::Row)>,
+        conn: &Connection,
+    ) -> AppResult<Vec<ParsedRecord<Self>>>;
+
+    fn to_csv_rows(&self) -> Vec<Self::Row>;
+
+    // Fields of a row holding numbers, only these are read with a decimal comma
+    fn numeric_fields() -> &'static [&'static str];
+}
+
+/// Full-text search over the text columns of a type, see the fts migration
+pub trait TextSearchable: Insertable {
+    fn fts_table() -> &'static str;
+}
+
 /// Insertion of an items into the database
 pub trait Insertable {
     // Checks id duplicates in db
@@ -32,18 +72,51 @@ pub trait Insertable {
     }
 
     fn table_name() -> &'static str;
+    // Name of the entity in the audit log
+    fn entity_name() -> &'static str {
+        Self::table_name()
+    }
     fn columns() -> Vec<&'static str>;
+    // Applies one column of a merge patch to the JSON form of a record
+    fn patch_field(record: &mut Value, column: &str, value: Value) {
+        record[column] = value;
+    }
     fn id_column() -> &'static str;
     fn id_value(&self) -> i32;
+    fn set_id_value(&mut self, id_value: i32);
 
     // Returns all values for specific type
     fn values(&self) -> Vec<ToSqlOutput<'_>>;
 
-    fn post_insert(&self, _conn: &Connection) -> Result<()> {
+    // Hooks run inside the transaction of the surrounding write operation
+    fn post_insert(&self, _tx: &Transaction) -> AppResult<()> {
         Ok(()) // do nothing by default
     }
 
-    fn post_delete(_id_value: Option<&i32>, _conn: &Connection) -> Result<()> {
+    // Receives the stored record as it was before the update
+    fn post_update(&self, _previous: &Self, _tx: &Transaction) -> AppResult<()> {
         Ok(()) // do nothing by default
     }
+
+    // Live orders referencing the record, they block its deletion unless it cascades
+    fn dependent_orders(_id_value: i32, _tx: &Transaction) -> AppResult<Vec<i32>> {
+        Ok(Vec::new()) // nothing depends on a record by default
+    }
+
+    fn pre_delete(_id_value: Option<&i32>, _tx: &Transaction) -> AppResult<()> {
+        Ok(()) // do nothing by default
+    }
+
+    fn post_delete(_id_value: Option<&i32>, _tx: &Transaction) -> AppResult<()> {
+        Ok(()) // do nothing by default
+    }
+
+    fn post_restore(_id_value: i32, _tx: &Transaction) -> AppResult<()> {
+        Ok(()) // do nothing by default
+    }
+
+    // Removes the rows owned by the record before it is removed for good
+    fn pre_purge(_id_value: i32, _tx: &Transaction) -> AppResult<()> {
+        Ok(()) // nothing owned by default
+    }
 }
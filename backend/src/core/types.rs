@@ -1,23 +1,37 @@
 // types.rs
-use crate::core::operations::{fetch_order_items, find_record_by_id};
-use crate::core::traits::{Insertable, Mappable, Searchable};
+use crate::core::operations::{
+    adjust_stock, apply_order_stock, fetch_order_items, fetch_order_items_by_order,
+    find_record_by_id, find_records_by_ids, not_found, record_status_change, revert_order_stock,
+};
+use crate::core::error::{AppError, AppResult};
+use crate::core::import::ParsedRecord;
+use crate::core::traits::{CsvRecord, Insertable, Mappable, Searchable, TextSearchable};
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::types::{Null, ToSqlOutput};
-use rusqlite::{params, Connection, Error, Result, Row};
-use serde::{Deserialize, Serialize};
+use rusqlite::{params, Connection, Error, Result, Row, Rows, Transaction};
+use serde::{Deserialize, Deserializer, Serialize};
 use utoipa::ToSchema;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
 use strum_macros::{Display, EnumString};
 
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+// IDs may be omitted (or null) in requests, 0 marks them to be assigned by the server
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<i32, D::Error> {
+    Ok(Option::<i32>::deserialize(deserializer)?.unwrap_or(0))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Article {
+    #[serde(default, deserialize_with = "deserialize_id")]
     pub article_id: i32,
     pub name: String,
     pub price: f64,
     pub manufacturer: String,
     pub stock: i32,
     pub category: Option<String>,
+    // Set when the article was soft deleted, never taken from requests
+    #[serde(default, skip_deserializing)]
+    pub deleted_at: Option<String>,
 }
 
 impl Article {
@@ -36,25 +50,29 @@ impl Article {
             manufacturer,
             stock,
             category,
+            deleted_at: None,
         }
     }
 }
 
 impl Mappable for Article {
-    fn from_row(row: &Row, _conn: &Connection) -> Result<Self> {
-        Ok(Article::new(
-            row.get(0)?,
-            row.get(1)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(4)?,
-            row.get(5)?,
-        ))
+    fn from_row(row: &Row, _conn: &Connection) -> AppResult<Self> {
+        Ok(Article {
+            deleted_at: row.get("deleted_at")?,
+            ..Article::new(
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            )
+        })
     }
 }
 
 impl Searchable for Article {
-    fn search(conn: &Connection, id: i32) -> Result<Self>
+    fn search(conn: &Connection, id: i32) -> AppResult<Self>
     where
         Self: Sized,
     {
@@ -82,6 +100,9 @@ impl Insertable for Article {
     fn id_value(&self) -> i32 {
         self.article_id
     }
+    fn set_id_value(&mut self, id_value: i32) {
+        self.article_id = id_value;
+    }
 
     fn values(&self) -> Vec<ToSqlOutput<'_>> {
         vec![
@@ -97,26 +118,54 @@ impl Insertable for Article {
         ]
     }
 
-    fn post_delete(id_value: Option<&i32>, conn: &Connection) -> Result<()> {
-        if let Some(article_id) = id_value {
-            conn.execute(
-                "DELETE FROM order_article WHERE article_id = ?1",
-                params![article_id],
-            )?;
-
-            // Delete all orders that no longer have any linked articles after the previous deletion
-            conn.execute(
-            "DELETE FROM orders WHERE order_id NOT IN (SELECT DISTINCT order_id FROM order_article)",
-            params![],
+    fn dependent_orders(id_value: i32, tx: &Transaction) -> AppResult<Vec<i32>> {
+        let mut stmt = tx.prepare(
+            "SELECT DISTINCT o.order_id FROM orders o
+             JOIN order_article oa ON oa.order_id = o.order_id
+             WHERE oa.article_id = ?1 AND o.deleted_at IS NULL
+             ORDER BY o.order_id",
         )?;
-        }
+        let order_ids = stmt
+            .query_map([id_value], |row| row.get(0))?
+            .collect::<Result<Vec<i32>>>()?;
+        Ok(order_ids)
+    }
+}
 
-        Ok(())
+impl CsvRecord for Article {
+    type Row = Article;
+
+    fn from_csv_rows(
+        rows: Vec<(usize, Article)>,
+        _conn: &Connection,
+    ) -> AppResult<Vec<ParsedRecord<Self>>> {
+        Ok(rows
+            .into_iter()
+            .map(|(line, row)| ParsedRecord {
+                lines: vec![line],
+                record: Ok(row),
+            })
+            .collect())
+    }
+
+    fn to_csv_rows(&self) -> Vec<Article> {
+        vec![self.clone()]
+    }
+
+    fn numeric_fields() -> &'static [&'static str] {
+        &["article_id", "price", "stock"]
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+impl TextSearchable for Article {
+    fn fts_table() -> &'static str {
+        "article_fts"
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Customer {
+    #[serde(default, deserialize_with = "deserialize_id")]
     pub customer_id: i32,
     pub first_name: String,
     pub last_name: String,
@@ -124,6 +173,8 @@ pub struct Customer {
     pub location: String,
     pub zip_code: i32,
     pub email: String,
+    #[serde(default, skip_deserializing)]
+    pub deleted_at: Option<String>,
 }
 
 impl Customer {
@@ -144,21 +195,25 @@ impl Customer {
             location,
             zip_code,
             email,
+            deleted_at: None,
         }
     }
 }
 
 impl Mappable for Customer {
-    fn from_row(row: &Row, _conn: &Connection) -> Result<Self> {
-        Ok(Customer::new(
-            row.get(0)?,
-            row.get(1)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(4)?,
-            row.get(5)?,
-            row.get(6)?,
-        ))
+    fn from_row(row: &Row, _conn: &Connection) -> AppResult<Self> {
+        Ok(Customer {
+            deleted_at: row.get("deleted_at")?,
+            ..Customer::new(
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+            )
+        })
     }
 }
 
@@ -185,6 +240,10 @@ impl Insertable for Customer {
         self.customer_id
     }
 
+    fn set_id_value(&mut self, id_value: i32) {
+        self.customer_id = id_value;
+    }
+
     fn values(&self) -> Vec<ToSqlOutput<'_>> {
         vec![
             self.customer_id.into(),
@@ -197,25 +256,21 @@ impl Insertable for Customer {
         ]
     }
 
-    fn post_delete(id_value: Option<&i32>, conn: &Connection) -> Result<()> {
-        if let Some(customer_id) = id_value {
-            conn.execute(
-            "DELETE FROM order_article WHERE order_id IN (SELECT order_id FROM orders WHERE customer_id = ?1)",
-            params![customer_id],
+    fn dependent_orders(id_value: i32, tx: &Transaction) -> AppResult<Vec<i32>> {
+        let mut stmt = tx.prepare(
+            "SELECT order_id FROM orders
+             WHERE customer_id = ?1 AND deleted_at IS NULL
+             ORDER BY order_id",
         )?;
-
-            conn.execute(
-                "DELETE FROM orders WHERE customer_id = ?1",
-                params![customer_id],
-            )?;
-        }
-
-        Ok(())
+        let order_ids = stmt
+            .query_map([id_value], |row| row.get(0))?
+            .collect::<Result<Vec<i32>>>()?;
+        Ok(order_ids)
     }
 }
 
 impl Searchable for Customer {
-    fn search(conn: &Connection, id: i32) -> Result<Self>
+    fn search(conn: &Connection, id: i32) -> AppResult<Self>
     where
         Self: Sized,
     {
@@ -223,19 +278,74 @@ impl Searchable for Customer {
     }
 }
 
+impl CsvRecord for Customer {
+    type Row = Customer;
+
+    fn from_csv_rows(
+        rows: Vec<(usize, Customer)>,
+        _conn: &Connection,
+    ) -> AppResult<Vec<ParsedRecord<Self>>> {
+        Ok(rows
+            .into_iter()
+            .map(|(line, row)| ParsedRecord {
+                lines: vec![line],
+                record: Ok(row),
+            })
+            .collect())
+    }
+
+    fn to_csv_rows(&self) -> Vec<Customer> {
+        vec![self.clone()]
+    }
+
+    fn numeric_fields() -> &'static [&'static str] {
+        &["customer_id", "zip_code"]
+    }
+}
+
+impl TextSearchable for Customer {
+    fn fts_table() -> &'static str {
+        "customer_fts"
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct OrderItem {
     pub article: Article,
     pub quantity: i32,
+    // Price and name of the article at the time the order was placed
+    #[serde(default)]
+    pub unit_price: Option<f64>,
+    #[serde(default)]
+    pub article_name: Option<String>,
 }
 
 impl OrderItem {
-    pub fn new(article: Article, quantity: i32) -> Self {
-        OrderItem { article, quantity }
+    pub fn new(
+        article: Article,
+        quantity: i32,
+        unit_price: Option<f64>,
+        article_name: Option<String>,
+    ) -> Self {
+        OrderItem {
+            article,
+            quantity,
+            unit_price,
+            article_name,
+        }
+    }
+
+    // Snapshotted price, falling back to the current article price for unsaved orders
+    pub fn price(&self) -> f64 {
+        self.unit_price.unwrap_or(self.article.price)
+    }
+
+    pub fn name(&self) -> &str {
+        self.article_name.as_deref().unwrap_or(&self.article.name)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Display, EnumString, ToSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display, EnumString, ToSchema)]
 pub enum OrderType {
     Return,
     Sale,
@@ -249,14 +359,23 @@ impl OrderType {
             _ => None,
         }
     }
+
+    // Change in article stock caused by an order line of this type
+    pub fn stock_delta(&self, quantity: i32) -> i32 {
+        match self {
+            OrderType::Sale => -quantity,
+            OrderType::Return => quantity,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Display, EnumString, ToSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display, EnumString, ToSchema)]
 pub enum OrderStatus {
     Pending,
     Completed,
     Shipped,
     Delivered,
+    Cancelled,
 }
 
 impl OrderStatus {
@@ -266,19 +385,37 @@ impl OrderStatus {
             "Completed" => Some(OrderStatus::Completed),
             "Shipped" => Some(OrderStatus::Shipped),
             "Delivered" => Some(OrderStatus::Delivered),
+            "Cancelled" => Some(OrderStatus::Cancelled),
             _ => None,
         }
     }
+
+    // Statuses an order may move to from this one, Delivered and Cancelled are final
+    pub fn allowed_transitions(&self) -> &'static [OrderStatus] {
+        match self {
+            OrderStatus::Pending => &[OrderStatus::Completed, OrderStatus::Cancelled],
+            OrderStatus::Completed => &[OrderStatus::Shipped, OrderStatus::Cancelled],
+            OrderStatus::Shipped => &[OrderStatus::Delivered],
+            OrderStatus::Delivered | OrderStatus::Cancelled => &[],
+        }
+    }
+
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        self.allowed_transitions().contains(&next)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct Order {
+    #[serde(default, deserialize_with = "deserialize_id")]
     pub order_id: i32,
     pub customer: Customer,
     pub items: Vec<OrderItem>,
     pub date: String,
     pub order_type: OrderType,
     pub status: OrderStatus,
+    #[serde(default, skip_deserializing)]
+    pub deleted_at: Option<String>,
 }
 
 impl Order {
@@ -297,35 +434,160 @@ impl Order {
             date,
             order_type,
             status,
+            deleted_at: None,
         }
     }
 }
 
-impl Mappable for Order {
-    fn from_row(row: &Row, conn: &Connection) -> Result<Self> {
-        let order_id = row.get(0)?;
-        let fetched_order_items = fetch_order_items(conn, order_id)?;
+impl Order {
+    // Every article may only appear in one line of an order, with a positive quantity
+    fn check_items(&self) -> AppResult<()> {
+        let mut seen = BTreeSet::new();
+        for order_item in &self.items {
+            if order_item.quantity <= 0 {
+                return Err(AppError::Validation(format!(
+                    "Quantity of article {} in order {} must be positive, got {}",
+                    order_item.article.article_id, self.order_id, order_item.quantity
+                )));
+            }
+            if !seen.insert(order_item.article.article_id) {
+                return Err(AppError::Validation(format!(
+                    "Article {} appears more than once in order {}",
+                    order_item.article.article_id, self.order_id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Price and name are copied from the current article row, so later edits
+    // of the article don't change the order
+    fn insert_line(&self, tx: &Transaction, order_item: &OrderItem) -> AppResult<()> {
+        let inserted = tx.execute(
+            "INSERT INTO order_article (order_id, article_id, quantity, unit_price, article_name)
+             SELECT ?1, article_id, ?2, price, name FROM article
+             WHERE article_id = ?3 AND deleted_at IS NULL",
+            params![
+                self.order_id,
+                order_item.quantity,
+                order_item.article.article_id
+            ],
+        )?;
+
+        if inserted == 0 {
+            return Err(AppError::Validation(format!(
+                "Article {} does not exist",
+                order_item.article.article_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Stock change per article caused by this order, cancelled orders hold no stock
+    fn stock_deltas(&self) -> BTreeMap<i32, i32> {
+        let mut deltas = BTreeMap::new();
+        if self.status == OrderStatus::Cancelled {
+            return deltas;
+        }
+
+        for order_item in &self.items {
+            *deltas.entry(order_item.article.article_id).or_insert(0) +=
+                self.order_type.stock_delta(order_item.quantity);
+        }
+        deltas
+    }
+}
+
+fn lines_by_article(items: &[OrderItem]) -> BTreeMap<i32, &OrderItem> {
+    items
+        .iter()
+        .map(|order_item| (order_item.article.article_id, order_item))
+        .collect()
+}
 
-        let customer_id = row.get(1)?;
-        let fetched_customer = find_record_by_id::<Customer>(conn, customer_id)?;
+// Columns of an orders row, before its customer and lines are attached
+struct OrderRow {
+    order_id: i32,
+    customer_id: i32,
+    date: String,
+    order_type: OrderType,
+    status: OrderStatus,
+    deleted_at: Option<String>,
+}
 
-        let date = row.get(2)?;
+impl OrderRow {
+    fn read(row: &Row) -> AppResult<Self> {
         let order_type: String = row.get(3)?;
         let status: String = row.get(4)?;
 
-        let order_type = OrderType::from_str(&order_type)
-            .ok_or_else(|| Error::InvalidParameterName("Invalid order_type".into()))?;
-        let status = OrderStatus::from_str(&status)
-            .ok_or_else(|| Error::InvalidParameterName("Invalid status".into()))?;
+        Ok(OrderRow {
+            order_id: row.get(0)?,
+            customer_id: row.get(1)?,
+            date: row.get(2)?,
+            order_type: OrderType::from_str(&order_type)
+                .ok_or_else(|| Error::InvalidParameterName("Invalid order_type".into()))?,
+            status: OrderStatus::from_str(&status)
+                .ok_or_else(|| Error::InvalidParameterName("Invalid status".into()))?,
+            deleted_at: row.get("deleted_at")?,
+        })
+    }
 
-        Ok(Order::new(
-            order_id,
-            fetched_customer,
-            fetched_order_items,
-            date,
-            order_type,
-            status,
-        ))
+    fn into_order(self, customer: Customer, items: Vec<OrderItem>) -> Order {
+        Order {
+            deleted_at: self.deleted_at,
+            ..Order::new(
+                self.order_id,
+                customer,
+                items,
+                self.date,
+                self.order_type,
+                self.status,
+            )
+        }
+    }
+}
+
+impl Mappable for Order {
+    fn from_row(row: &Row, conn: &Connection) -> AppResult<Self> {
+        let order_row = OrderRow::read(row)?;
+        let fetched_order_items = fetch_order_items(conn, order_row.order_id)?;
+        let fetched_customer = find_record_by_id::<Customer>(conn, order_row.customer_id)?;
+
+        Ok(order_row.into_order(fetched_customer, fetched_order_items))
+    }
+
+    // Customers and lines of all orders are loaded with one query each
+    fn from_rows(rows: &mut Rows, conn: &Connection) -> AppResult<Vec<Self>> {
+        let mut order_rows = Vec::new();
+        while let Some(row) = rows.next()? {
+            order_rows.push(OrderRow::read(row)?);
+        }
+
+        let order_ids = order_rows.iter().map(|o| o.order_id).collect::<Vec<_>>();
+        let customer_ids = order_rows
+            .iter()
+            .map(|o| o.customer_id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let mut items_by_order = fetch_order_items_by_order(conn, &order_ids)?;
+        let customers = find_records_by_ids::<Customer>(conn, &customer_ids)?;
+
+        order_rows
+            .into_iter()
+            .map(|order_row| {
+                // Customers are shared between orders, so each order gets its own copy
+                let customer = customers
+                    .get(&order_row.customer_id)
+                    .cloned()
+                    .ok_or_else(|| not_found::<Customer>(order_row.customer_id))?;
+                let items = items_by_order.remove(&order_row.order_id).unwrap_or_default();
+
+                Ok(order_row.into_order(customer, items))
+            })
+            .collect()
     }
 }
 
@@ -333,9 +595,19 @@ impl Insertable for Order {
     fn table_name() -> &'static str {
         "orders"
     }
+    fn entity_name() -> &'static str {
+        "order"
+    }
     fn columns() -> Vec<&'static str> {
         vec!["order_id", "customer_id", "date", "order_type", "status"]
     }
+    // The customer is nested in the JSON form of an order
+    fn patch_field(record: &mut serde_json::Value, column: &str, value: serde_json::Value) {
+        match column {
+            "customer_id" => record["customer"]["customer_id"] = value,
+            _ => record[column] = value,
+        }
+    }
     fn id_column() -> &'static str {
         "order_id"
     }
@@ -344,6 +616,10 @@ impl Insertable for Order {
         self.order_id
     }
 
+    fn set_id_value(&mut self, id_value: i32) {
+        self.order_id = id_value;
+    }
+
     fn values(&self) -> Vec<ToSqlOutput<'_>> {
         vec![
             self.order_id.into(),
@@ -354,45 +630,231 @@ impl Insertable for Order {
         ]
     }
 
-    fn post_insert(&self, conn: &Connection) -> Result<()> {
-        let query = "
-            INSERT INTO order_article (order_id, article_id, quantity)
-            VALUES (?1, ?2, ?3)
-        ";
-
-        let mut stmt = conn.prepare(query)?;
+    fn post_insert(&self, tx: &Transaction) -> AppResult<()> {
+        self.check_items()?;
 
         for order_item in &self.items {
-            stmt.execute(params![
-                self.order_id,
-                order_item.article.article_id,
-                order_item.quantity
-            ])?;
+            self.insert_line(tx, order_item)?;
+        }
+
+        for (article_id, delta) in self.stock_deltas() {
+            adjust_stock(tx, article_id, delta)?;
+        }
+
+        record_status_change(tx, self.order_id, None, self.status)
+    }
+
+    // Persists added, removed and changed lines and moves only the difference in stock,
+    // so a status change to Cancelled gives back everything the order held
+    fn post_update(&self, previous: &Self, tx: &Transaction) -> AppResult<()> {
+        if previous.status != self.status {
+            record_status_change(tx, self.order_id, Some(previous.status), self.status)?;
+        }
+
+        self.check_items()?;
+
+        let old_lines = lines_by_article(&previous.items);
+        let new_lines = lines_by_article(&self.items);
+
+        for article_id in old_lines.keys().filter(|id| !new_lines.contains_key(id)) {
+            tx.execute(
+                "DELETE FROM order_article WHERE order_id = ?1 AND article_id = ?2",
+                params![self.order_id, article_id],
+            )?;
+        }
+
+        for (article_id, order_item) in &new_lines {
+            match old_lines.get(article_id) {
+                Some(old) if old.quantity == order_item.quantity => {}
+                Some(_) => {
+                    tx.execute(
+                        "UPDATE order_article SET quantity = ?1 WHERE order_id = ?2 AND article_id = ?3",
+                        params![order_item.quantity, self.order_id, article_id],
+                    )?;
+                }
+                None => self.insert_line(tx, order_item)?,
+            }
         }
 
+        let old_deltas = previous.stock_deltas();
+        let new_deltas = self.stock_deltas();
+        let article_ids = old_deltas.keys().chain(new_deltas.keys()).collect::<BTreeSet<_>>();
+
+        for article_id in article_ids {
+            let old = old_deltas.get(article_id).copied().unwrap_or(0);
+            let new = new_deltas.get(article_id).copied().unwrap_or(0);
+            if new != old {
+                adjust_stock(tx, *article_id, new - old)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Deleted orders no longer hold their stock, restoring them takes it again
+    fn pre_delete(id_value: Option<&i32>, tx: &Transaction) -> AppResult<()> {
+        let order_ids = match id_value {
+            Some(id_value) => vec![*id_value],
+            None => {
+                let mut stmt = tx.prepare("SELECT order_id FROM orders WHERE deleted_at IS NULL")?;
+                let ids = stmt
+                    .query_map([], |row| row.get::<_, i32>(0))?
+                    .collect::<Result<Vec<_>>>()?;
+                ids
+            }
+        };
+
+        for order_id in order_ids {
+            revert_order_stock(tx, order_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn post_restore(id_value: i32, tx: &Transaction) -> AppResult<()> {
+        apply_order_stock(tx, id_value)
+    }
+
+    fn pre_purge(id_value: i32, tx: &Transaction) -> AppResult<()> {
+        tx.execute("DELETE FROM order_article WHERE order_id = ?1", [id_value])?;
+        tx.execute("DELETE FROM order_status_history WHERE order_id = ?1", [id_value])?;
         Ok(())
     }
+}
+
+/// One line of an order in a CSV file, the order values are repeated on every line
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OrderCsvRow {
+    pub order_id: i32,
+    pub customer_id: i32,
+    pub date: String,
+    pub order_type: OrderType,
+    pub status: OrderStatus,
+    pub article_id: i32,
+    pub quantity: i32,
+}
+
+// Builds one order from its lines, which all have to repeat the same order values
+fn order_from_csv_lines(
+    lines: Vec<(usize, OrderCsvRow)>,
+    customers: &HashMap<i32, Customer>,
+    articles: &HashMap<i32, Article>,
+) -> AppResult<Order> {
+    let mut order: Option<Order> = None;
 
-    fn post_delete(id_value: Option<&i32>, conn: &Connection) -> Result<()> {
-        match id_value {
-            Some(id_value) => {
-                let query = "DELETE FROM order_article WHERE order_id = ?1";
+    for (line, row) in lines {
+        let article = articles
+            .get(&row.article_id)
+            .filter(|article| article.deleted_at.is_none())
+            .ok_or_else(|| {
+                AppError::Validation(format!(
+                    "Line {}: article {} does not exist",
+                    line, row.article_id
+                ))
+            })?;
+        let order_item = OrderItem::new(article.clone(), row.quantity, None, None);
 
-                conn.execute(query, params![id_value])?;
+        match order.as_mut() {
+            Some(order) => {
+                if order.customer.customer_id != row.customer_id
+                    || order.date != row.date
+                    || order.order_type != row.order_type
+                    || order.status != row.status
+                {
+                    return Err(AppError::Validation(format!(
+                        "Line {}: customer_id, date, order_type and status differ from the first line of order {}",
+                        line, row.order_id
+                    )));
+                }
+                order.items.push(order_item);
             }
             None => {
-                let query = "DELETE FROM order_article";
+                let customer = customers
+                    .get(&row.customer_id)
+                    .filter(|customer| customer.deleted_at.is_none())
+                    .ok_or_else(|| {
+                        AppError::Validation(format!(
+                            "Line {}: customer {} does not exist",
+                            line, row.customer_id
+                        ))
+                    })?;
 
-                conn.execute(query, params![])?;
+                order = Some(Order::new(
+                    row.order_id,
+                    customer.clone(),
+                    vec![order_item],
+                    row.date,
+                    row.order_type,
+                    row.status,
+                ));
             }
         }
+    }
 
-        Ok(())
+    order.ok_or_else(|| AppError::Internal("Order without lines".into()))
+}
+
+impl CsvRecord for Order {
+    type Row = OrderCsvRow;
+
+    // Lines are grouped into orders by order_id, in the order of their first line
+    fn from_csv_rows(
+        rows: Vec<(usize, OrderCsvRow)>,
+        conn: &Connection,
+    ) -> AppResult<Vec<ParsedRecord<Self>>> {
+        let customer_ids = rows.iter().map(|(_, r)| r.customer_id).collect::<BTreeSet<_>>();
+        let article_ids = rows.iter().map(|(_, r)| r.article_id).collect::<BTreeSet<_>>();
+        let customers = find_records_by_ids::<Customer>(
+            conn,
+            &customer_ids.into_iter().collect::<Vec<_>>(),
+        )?;
+        let articles =
+            find_records_by_ids::<Article>(conn, &article_ids.into_iter().collect::<Vec<_>>())?;
+
+        let mut groups: Vec<Vec<(usize, OrderCsvRow)>> = Vec::new();
+        let mut positions: HashMap<i32, usize> = HashMap::new();
+        for (line, row) in rows {
+            match positions.get(&row.order_id) {
+                Some(&position) => groups[position].push((line, row)),
+                None => {
+                    positions.insert(row.order_id, groups.len());
+                    groups.push(vec![(line, row)]);
+                }
+            }
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|lines| ParsedRecord {
+                lines: lines.iter().map(|(line, _)| *line).collect(),
+                record: order_from_csv_lines(lines, &customers, &articles),
+            })
+            .collect())
+    }
+
+    // Orders without lines have no rows
+    fn to_csv_rows(&self) -> Vec<OrderCsvRow> {
+        self.items
+            .iter()
+            .map(|order_item| OrderCsvRow {
+                order_id: self.order_id,
+                customer_id: self.customer.customer_id,
+                date: self.date.clone(),
+                order_type: self.order_type,
+                status: self.status,
+                article_id: order_item.article.article_id,
+                quantity: order_item.quantity,
+            })
+            .collect()
+    }
+
+    fn numeric_fields() -> &'static [&'static str] {
+        &["order_id", "customer_id", "article_id", "quantity"]
     }
 }
 
 impl Searchable for Order {
-    fn search(conn: &Connection, id: i32) -> Result<Self>
+    fn search(conn: &Connection, id: i32) -> AppResult<Self>
     where
         Self: Sized,
     {
@@ -480,4 +942,638 @@ pub enum ApiResponse {
 
 
 
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub entity: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub actor: String,
+    pub timestamp: String,
+    // Changed fields as { "field": { "before": ..., "after": ... } }
+    #[schema(value_type = Object)]
+    pub changes: serde_json::Value,
+}
+
+impl AuditEntry {
+    pub fn new(
+        id: i64,
+        entity: String,
+        entity_id: i32,
+        action: String,
+        actor: String,
+        timestamp: String,
+        changes: serde_json::Value,
+    ) -> Self {
+        AuditEntry {
+            id,
+            entity,
+            entity_id,
+            action,
+            actor,
+            timestamp,
+            changes,
+        }
+    }
+}
+
+/// One entry of the status history of an order, `from_status` is empty for the initial status
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct StatusChange {
+    pub order_id: i32,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub changed_at: String,
+}
+
+impl StatusChange {
+    pub fn new(
+        order_id: i32,
+        from_status: Option<String>,
+        to_status: String,
+        changed_at: String,
+    ) -> Self {
+        StatusChange {
+            order_id,
+            from_status,
+            to_status,
+            changed_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RowStatus {
+    Ok,
+    Skipped,
+    Duplicate,
+    Invalid,
+}
+
+// What an import did with a row that passed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display, EnumString, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum RowAction {
+    Inserted,
+    Updated,
+}
+
+/// Outcome of one row of an import file, line numbers count the header as line 1
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RowReport {
+    pub line: usize,
+    pub status: RowStatus,
+    pub action: Option<RowAction>,
+    pub reason: Option<String>,
+}
+
+impl RowReport {
+    pub fn new(
+        line: usize,
+        status: RowStatus,
+        action: Option<RowAction>,
+        reason: Option<String>,
+    ) -> Self {
+        RowReport {
+            line,
+            status,
+            action,
+            reason,
+        }
+    }
+}
+
+/// Result of an import with the outcome of every row
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    // Whether the rows marked ok were stored
+    pub committed: bool,
+    // Whether the import was cancelled, rows it didn't get to aren't reported
+    pub cancelled: bool,
+    // Import batch of the stored rows, set once they are committed
+    pub batch_id: Option<i64>,
+    pub total: usize,
+    pub ok: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub duplicate: usize,
+    pub invalid: usize,
+    pub rows: Vec<RowReport>,
+}
+
+impl ImportReport {
+    pub fn new(dry_run: bool, rows: Vec<RowReport>) -> Self {
+        let count = |status| rows.iter().filter(|row| row.status == status).count();
+        let count_action =
+            |action| rows.iter().filter(|row| row.action == Some(action)).count();
+
+        ImportReport {
+            dry_run,
+            committed: false,
+            cancelled: false,
+            batch_id: None,
+            total: rows.len(),
+            ok: count(RowStatus::Ok),
+            inserted: count_action(RowAction::Inserted),
+            updated: count_action(RowAction::Updated),
+            skipped: count(RowStatus::Skipped),
+            duplicate: count(RowStatus::Duplicate),
+            invalid: count(RowStatus::Invalid),
+            rows,
+        }
+    }
+
+    // Every row was stored or deliberately skipped
+    pub fn passed(&self) -> bool {
+        self.ok + self.skipped == self.total
+    }
+}
+
+/// A committed import run with the number of records it inserted and updated
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ImportBatch {
+    pub id: i64,
+    pub entity: String,
+    pub file_name: Option<String>,
+    pub row_count: i64,
+    pub actor: String,
+    pub created_at: String,
+    pub reverted_at: Option<String>,
+    pub inserted: i64,
+    pub updated: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+/// Background job with its progress, the report is set once the import is done
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Job {
+    pub job_id: u32,
+    pub kind: String,
+    pub status: JobStatus,
+    // Rows of the file and how many of them were handled so far
+    pub total: usize,
+    pub processed: usize,
+    // Why a failed job stopped, errors of single rows are in the report
+    pub error: Option<String>,
+    pub report: Option<ImportReport>,
+}
+
+impl Job {
+    pub fn new(job_id: u32, kind: String) -> Self {
+        Job {
+            job_id,
+            kind,
+            status: JobStatus::Queued,
+            total: 0,
+            processed: 0,
+            error: None,
+            report: None,
+        }
+    }
+}
+
+/// One page of a list request together with the number of all matching records
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
 pub type DbPool = Arc<r2d2::Pool<SqliteConnectionManager>>;
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::core::migrations::run_migrations;
+    use crate::core::operations::{
+        delete_record_by_id, fetch_records, in_transaction, insert_record, restore_record,
+        update_record, DeletePolicy,
+    };
+    use crate::core::query::ListQuery;
+    use std::cell::Cell;
+
+    // Article 1 with 5 and article 2 with 100 in stock, customer 1 and no orders
+    pub fn test_database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO article (article_id, name, price, manufacturer, stock, category)
+            VALUES (1, 'Hammer', 12.5, 'Acme', 5, 'Tools'),
+                   (2, 'Nails', 0.1, 'Acme', 100, NULL);
+
+            INSERT INTO customer (customer_id, first_name, last_name, street, location, zip_code, email)
+            VALUES (1, 'Erika', 'Müller', 'Hauptstraße 1', 'Berlin', 10115, 'erika@example.com');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    // Unsaved order of customer 1 with (article_id, quantity) lines
+    pub fn new_order(conn: &Connection, order_type: OrderType, lines: &[(i32, i32)]) -> Order {
+        let items = lines
+            .iter()
+            .map(|&(article_id, quantity)| {
+                let article = find_record_by_id::<Article>(conn, article_id).unwrap();
+                OrderItem::new(article, quantity, None, None)
+            })
+            .collect();
+
+        Order::new(
+            0,
+            find_record_by_id(conn, 1).unwrap(),
+            items,
+            "2024-01-15".to_string(),
+            order_type,
+            OrderStatus::Pending,
+        )
+    }
+
+    // Stores a new order like POST /orders/add
+    pub fn place(
+        conn: &mut Connection,
+        order_type: OrderType,
+        lines: &[(i32, i32)],
+    ) -> AppResult<i32> {
+        let mut order = new_order(conn, order_type, lines);
+        in_transaction(conn, |tx| insert_record(tx, &mut order, "test"))
+    }
+
+    pub fn stock(conn: &Connection, article_id: i32) -> i32 {
+        find_record_by_id::<Article>(conn, article_id)
+            .unwrap()
+            .stock
+    }
+
+    #[test]
+    fn sales_take_stock_and_returns_give_it_back() {
+        let mut conn = test_database();
+
+        place(&mut conn, OrderType::Sale, &[(1, 2), (2, 30)]).unwrap();
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (3, 70));
+
+        place(&mut conn, OrderType::Return, &[(1, 1)]).unwrap();
+        assert_eq!(stock(&conn, 1), 4);
+    }
+
+    #[test]
+    fn sales_beyond_the_stock_are_rejected_as_a_whole() {
+        let mut conn = test_database();
+
+        let result = place(&mut conn, OrderType::Sale, &[(1, 2), (2, 101)]);
+        assert!(matches!(
+            result,
+            Err(AppError::InsufficientStock {
+                article_id: 2,
+                requested: 101,
+                available: 100
+            })
+        ));
+
+        // Stock already taken for article 1 was rolled back with the rest of the order
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (5, 100));
+        let orders: i64 = conn
+            .query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(orders, 0);
+    }
+
+    #[test]
+    fn lines_without_a_positive_quantity_are_rejected() {
+        let mut conn = test_database();
+
+        for quantity in [0, -10] {
+            assert!(matches!(
+                place(&mut conn, OrderType::Sale, &[(1, quantity)]),
+                Err(AppError::Validation(_))
+            ));
+        }
+        assert_eq!(stock(&conn, 1), 5);
+    }
+
+    #[test]
+    fn deleting_an_order_gives_its_stock_back_and_restoring_takes_it_again() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 4)]).unwrap();
+        assert_eq!(stock(&conn, 1), 1);
+
+        in_transaction(&mut conn, |tx| {
+            delete_record_by_id::<Order>(tx, &Some(order_id), DeletePolicy::Restrict, "test")
+        })
+        .unwrap();
+        assert_eq!(stock(&conn, 1), 5);
+
+        in_transaction(&mut conn, |tx| {
+            restore_record::<Order>(tx, order_id, "test")
+        })
+        .unwrap();
+        assert_eq!(stock(&conn, 1), 1);
+    }
+
+    const ORDERS: i32 = 3000;
+    const CUSTOMERS: i32 = 100;
+    const ARTICLES: i32 = 50;
+
+    thread_local! {
+        static STATEMENTS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count_statement(_sql: &str) {
+        STATEMENTS.with(|count| count.set(count.get() + 1));
+    }
+
+    // Orders with two lines each, spread over a few customers and articles
+    fn seeded_database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let tx = conn.transaction().unwrap();
+        for article_id in 1..=ARTICLES {
+            tx.execute(
+                "INSERT INTO article (article_id, name, price, manufacturer, stock, category)
+                 VALUES (?1, ?2, ?3, 'Acme', 1000, 'Tools')",
+                params![
+                    article_id,
+                    format!("Article {}", article_id),
+                    article_id as f64
+                ],
+            )
+            .unwrap();
+        }
+        for customer_id in 1..=CUSTOMERS {
+            tx.execute(
+                "INSERT INTO customer (customer_id, first_name, last_name, street, location, zip_code, email)
+                 VALUES (?1, 'First', ?2, 'Street 1', 'Town', 12345, 'mail@example.com')",
+                params![customer_id, format!("Customer {}", customer_id)],
+            )
+            .unwrap();
+        }
+        for order_id in 1..=ORDERS {
+            tx.execute(
+                "INSERT INTO orders (order_id, customer_id, date, order_type, status)
+                 VALUES (?1, ?2, '2024-01-15', 'Sale', 'Pending')",
+                params![order_id, order_id % CUSTOMERS + 1],
+            )
+            .unwrap();
+            for article_id in [order_id % ARTICLES + 1, (order_id + 1) % ARTICLES + 1] {
+                tx.execute(
+                    "INSERT INTO order_article (order_id, article_id, quantity, unit_price, article_name)
+                     VALUES (?1, ?2, ?3, 1.5, 'Old name')",
+                    params![order_id, article_id, order_id % 7 + 1],
+                )
+                .unwrap();
+            }
+        }
+        tx.commit().unwrap();
+
+        conn
+    }
+
+    fn fetch_orders(conn: &mut Connection, limit: Option<&str>) -> (Page<Order>, usize) {
+        let mut params = HashMap::new();
+        if let Some(limit) = limit {
+            params.insert("limit".to_string(), limit.to_string());
+        }
+        let query = ListQuery::parse::<Order>(&params).unwrap();
+
+        STATEMENTS.with(|count| count.set(0));
+        conn.trace(Some(count_statement));
+        let page = fetch_records::<Order>(conn, &query).unwrap();
+        conn.trace(None);
+
+        (page, STATEMENTS.with(Cell::get))
+    }
+
+    #[test]
+    fn fetching_thousands_of_orders_needs_a_constant_number_of_statements() {
+        let mut conn = seeded_database();
+
+        let (_, few_statements) = fetch_orders(&mut conn, Some("10"));
+
+        let (page, statements) = fetch_orders(&mut conn, None);
+
+        // Count, orders, their lines and their customers
+        assert_eq!(statements, 4);
+        assert_eq!(few_statements, statements);
+
+        assert_eq!(page.total, ORDERS as i64);
+        assert_eq!(page.items.len(), ORDERS as usize);
+
+        for order in &page.items {
+            assert_eq!(order.customer.customer_id, order.order_id % CUSTOMERS + 1);
+            assert_eq!(
+                order.customer.last_name,
+                format!("Customer {}", order.customer.customer_id)
+            );
+
+            let mut article_ids = order
+                .items
+                .iter()
+                .map(|item| item.article.article_id)
+                .collect::<Vec<_>>();
+            article_ids.sort();
+            let mut expected = vec![
+                order.order_id % ARTICLES + 1,
+                (order.order_id + 1) % ARTICLES + 1,
+            ];
+            expected.sort();
+            assert_eq!(article_ids, expected);
+
+            for item in &order.items {
+                assert_eq!(item.quantity, order.order_id % 7 + 1);
+                assert_eq!(item.unit_price, Some(1.5));
+                assert_eq!(item.article_name.as_deref(), Some("Old name"));
+                assert_eq!(
+                    item.article.name,
+                    format!("Article {}", item.article.article_id)
+                );
+            }
+        }
+    }
+
+    fn update(conn: &mut Connection, order: &Order) -> AppResult<()> {
+        in_transaction(conn, |tx| update_record(tx, order, "test"))
+    }
+
+    fn lines(conn: &Connection, order_id: i32) -> Vec<(i32, i32)> {
+        find_record_by_id::<Order>(conn, order_id)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| (item.article.article_id, item.quantity))
+            .collect()
+    }
+
+    #[test]
+    fn updates_store_changed_added_and_removed_lines() {
+        let mut conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO article (article_id, name, price, manufacturer, stock, category)
+             VALUES (3, 'Saw', 20.0, 'Acme', 10, NULL)",
+        )
+        .unwrap();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 2), (2, 10)]).unwrap();
+
+        let mut order = find_record_by_id::<Order>(&conn, order_id).unwrap();
+        order.items = new_order(&conn, OrderType::Sale, &[(1, 4), (3, 1)]).items;
+        update(&mut conn, &order).unwrap();
+
+        assert_eq!(lines(&conn, order_id), vec![(1, 4), (3, 1)]);
+        assert_eq!(
+            (stock(&conn, 1), stock(&conn, 2), stock(&conn, 3)),
+            (1, 100, 9)
+        );
+    }
+
+    #[test]
+    fn updates_keep_the_price_of_unchanged_lines() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 2)]).unwrap();
+        conn.execute("UPDATE article SET price = 99.0 WHERE article_id = 1", [])
+            .unwrap();
+
+        let mut order = find_record_by_id::<Order>(&conn, order_id).unwrap();
+        order.items[0].quantity = 3;
+        update(&mut conn, &order).unwrap();
+
+        let order = find_record_by_id::<Order>(&conn, order_id).unwrap();
+        assert_eq!(order.items[0].unit_price, Some(12.5));
+        assert_eq!(order.items[0].article.price, 99.0);
+    }
+
+    #[test]
+    fn updates_beyond_the_stock_change_nothing() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 2), (2, 10)]).unwrap();
+
+        let mut order = find_record_by_id::<Order>(&conn, order_id).unwrap();
+        order.items = new_order(&conn, OrderType::Sale, &[(1, 6)]).items;
+        assert!(matches!(
+            update(&mut conn, &order),
+            Err(AppError::InsufficientStock { .. })
+        ));
+
+        assert_eq!(lines(&conn, order_id), vec![(1, 2), (2, 10)]);
+        assert_eq!((stock(&conn, 1), stock(&conn, 2)), (3, 90));
+    }
+
+    #[test]
+    fn changing_the_order_type_moves_the_stock_the_other_way() {
+        let mut conn = test_database();
+        let order_id = place(&mut conn, OrderType::Sale, &[(1, 2)]).unwrap();
+
+        let mut order = find_record_by_id::<Order>(&conn, order_id).unwrap();
+        order.order_type = OrderType::Return;
+        update(&mut conn, &order).unwrap();
+
+        assert_eq!(stock(&conn, 1), 7);
+    }
+
+    fn csv_row(order_id: i32, customer_id: i32, article_id: i32, quantity: i32) -> OrderCsvRow {
+        OrderCsvRow {
+            order_id,
+            customer_id,
+            date: "2024-03-01".into(),
+            order_type: OrderType::Sale,
+            status: OrderStatus::Pending,
+            article_id,
+            quantity,
+        }
+    }
+
+    fn validation_error(record: &AppResult<Order>) -> String {
+        match record {
+            Err(AppError::Validation(message)) => message.clone(),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn csv_lines_are_grouped_by_order_id() {
+        let conn = test_database();
+        let rows = vec![
+            (2, csv_row(7, 1, 1, 2)),
+            (3, csv_row(8, 1, 2, 10)),
+            (4, csv_row(7, 1, 2, 30)),
+        ];
+
+        let parsed = Order::from_csv_rows(rows, &conn).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].lines, vec![2, 4]);
+        assert_eq!(parsed[1].lines, vec![3]);
+
+        let order = parsed[0].record.as_ref().unwrap();
+        assert_eq!(order.order_id, 7);
+        assert_eq!(order.customer.customer_id, 1);
+        let lines: Vec<_> = order
+            .items
+            .iter()
+            .map(|item| (item.article.article_id, item.quantity))
+            .collect();
+        assert_eq!(lines, vec![(1, 2), (2, 30)]);
+
+        // Written back, an order gives the rows it was read from
+        let written = order.to_csv_rows();
+        assert_eq!(written.len(), 2);
+        assert_eq!((written[1].order_id, written[1].article_id), (7, 2));
+    }
+
+    #[test]
+    fn csv_lines_with_differing_order_values_are_rejected() {
+        let conn = test_database();
+        let mut other_date = csv_row(7, 1, 2, 30);
+        other_date.date = "2024-03-02".into();
+        let rows = vec![
+            (2, csv_row(7, 1, 1, 2)),
+            (3, other_date),
+            (4, csv_row(8, 1, 2, 10)),
+        ];
+
+        let parsed = Order::from_csv_rows(rows, &conn).unwrap();
+
+        assert_eq!(parsed[0].lines, vec![2, 3]);
+        assert!(validation_error(&parsed[0].record).starts_with("Line 3:"));
+        // Other orders of the file are still read
+        assert!(parsed[1].record.is_ok());
+    }
+
+    #[test]
+    fn csv_lines_with_unknown_customers_or_articles_are_rejected() {
+        let conn = test_database();
+        let rows = vec![(2, csv_row(7, 9, 1, 2)), (3, csv_row(8, 1, 9, 1))];
+
+        let parsed = Order::from_csv_rows(rows, &conn).unwrap();
+
+        assert_eq!(
+            validation_error(&parsed[0].record),
+            "Line 2: customer 9 does not exist"
+        );
+        assert_eq!(
+            validation_error(&parsed[1].record),
+            "Line 3: article 9 does not exist"
+        );
+    }
+}
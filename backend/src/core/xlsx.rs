@@ -0,0 +1,110 @@
+// xlsx.rs
+use crate::core::error::{AppError, AppResult};
+use crate::core::import::{deserialize_rows, map_headers, CsvDialect, ImportRow};
+use crate::core::operations::fetch_records;
+use crate::core::query::ListQuery;
+use crate::core::traits::{CsvRecord, Insertable, Mappable};
+use calamine::{open_workbook_from_rs, Data, Reader, Xlsx};
+use csv::StringRecord;
+use rusqlite::Connection;
+use rust_xlsxwriter::{Format, Workbook};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Cursor;
+
+// Sheets are named after the entity, e.g. "articles"
+fn sheet_name<T: Insertable>() -> String {
+    format!("{}s", T::entity_name())
+}
+
+/// Adds a sheet with all live records in the format of the CSV export.
+/// Numbers are written as number cells and text as text cells, so Excel doesn't
+/// convert text that looks like a number or a date.
+pub fn add_sheet<T>(workbook: &mut Workbook, conn: &Connection) -> AppResult<()>
+where
+    T: Mappable + Insertable + CsvRecord + Debug,
+{
+    let query = ListQuery::parse::<T>(&HashMap::new())?;
+    let page = fetch_records::<T>(conn, &query)?;
+
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet_name::<T>()).map_err(xlsx_error)?;
+    worksheet
+        .deserialize_headers_with_format::<T::Row>(0, 0, &Format::new().set_bold())
+        .map_err(xlsx_error)?;
+
+    for row in page.items.iter().flat_map(|record| record.to_csv_rows()) {
+        worksheet.serialize(&row).map_err(xlsx_error)?;
+    }
+    worksheet.autofit();
+
+    Ok(())
+}
+
+pub fn xlsx_error(e: rust_xlsxwriter::XlsxError) -> AppError {
+    AppError::Internal(format!("Failed to write XLSX: {}", e))
+}
+
+/// Reads the rows of the entity's sheet, or of the first sheet if there is none with
+/// its name. Line numbers are the row numbers shown by Excel.
+pub fn read_xlsx<T: CsvRecord + Insertable>(
+    data: &[u8],
+    dialect: &CsvDialect,
+) -> AppResult<Vec<ImportRow<T::Row>>> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(data))
+        .map_err(|e| AppError::Validation(format!("Failed to read XLSX file: {}", e)))?;
+
+    let names = workbook.sheet_names();
+    let sheet = names
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(&sheet_name::<T>()))
+        .or(names.first())
+        .cloned()
+        .ok_or_else(|| AppError::Validation("XLSX file contains no sheet".into()))?;
+
+    let range = workbook
+        .worksheet_range(&sheet)
+        .map_err(|e| AppError::Validation(format!("Failed to read sheet {}: {}", sheet, e)))?;
+    let first_row = range.start().map_or(0, |(row, _)| row as usize);
+
+    let mut rows = range.rows();
+    let headers = map_headers(
+        &rows.next().map(to_record).unwrap_or_default(),
+        &dialect.mapping,
+    )?;
+
+    // Header in the first row of the range, rows are counted from 1
+    let records = rows
+        .enumerate()
+        .filter(|(_, cells)| cells.iter().any(|cell| *cell != Data::Empty))
+        .map(|(index, cells)| (first_row + index + 2, Ok(to_record(cells))))
+        .collect();
+
+    Ok(deserialize_rows::<T>(&headers, records, b'.'))
+}
+
+fn to_record(cells: &[Data]) -> StringRecord {
+    cells.iter().map(cell_text).collect()
+}
+
+// Text of a cell as it would appear in a CSV file
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(text) | Data::DateTimeIso(text) | Data::DurationIso(text) => text.clone(),
+        // Excel stores all numbers as floats, whole ones are needed as integers for IDs
+        Data::Float(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+            (*number as i64).to_string()
+        }
+        Data::Float(number) => number.to_string(),
+        Data::Int(number) => number.to_string(),
+        Data::Bool(value) => value.to_string(),
+        // Dates typed into Excel become ISO dates like the ones of the export
+        Data::DateTime(date) => match date.as_datetime() {
+            Some(datetime) if date.as_f64().fract() == 0.0 => datetime.date().to_string(),
+            Some(datetime) => datetime.to_string(),
+            None => date.to_string(),
+        },
+        Data::Error(e) => e.to_string(),
+    }
+}
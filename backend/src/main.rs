@@ -2,9 +2,17 @@
 mod api;
 mod core;
 use api::server;
+use std::env;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `backend migrate` only applies pending schema migrations and exits
+    if env::args().nth(1).as_deref() == Some("migrate") {
+        let version = server::migrate_database()?;
+        println!("Database schema is at version {}", version);
+        return Ok(());
+    }
+
     server::start_api_server().await;
     Ok(())
 }
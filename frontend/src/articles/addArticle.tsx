@@ -13,9 +13,9 @@ export const AddArticle = () => {
     {
       label: "Article ID",
       name: "article_id",
-      placeholder: "Article ID",
+      placeholder: "Article ID (optional)",
       valueAsNumber: true,
-      required: true,
+      required: false,
     },
     {
       label: "Name",
@@ -54,7 +54,7 @@ export const AddArticle = () => {
     try {
       const request = await addArticle(newData);
       await fetchArticles();
-      setState(StateKeys.ArticleData, [newData, ...(articleData ?? [])]);
+      setState(StateKeys.ArticleData, [request.data, ...(articleData ?? [])]);
       setNotification({ success: request.message, error: null });
       console.log("Article added successfully");
       setTimeout(() => setNotification({ success: null, error: null }), 5000);
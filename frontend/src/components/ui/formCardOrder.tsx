@@ -119,6 +119,9 @@ export function FormCardOrder<TFormValues extends FieldValues>({
                           <SelectItem value={DeliveryStatus.Delivered}>
                             <Badge className="bg-teal-700">Delivered</Badge>
                           </SelectItem>
+                          <SelectItem value={DeliveryStatus.Cancelled}>
+                            <Badge className="bg-red-700">Cancelled</Badge>
+                          </SelectItem>
                         </SelectContent>
                       </Select>
                     )}
@@ -14,9 +14,9 @@ export const AddCustomer = () => {
     {
       label: "Customer ID",
       name: "customer_id",
-      placeholder: "Customer ID",
+      placeholder: "Customer ID (optional)",
       valueAsNumber: true,
-      required: true,
+      required: false,
     },
     {
       label: "First name",
@@ -61,7 +61,7 @@ export const AddCustomer = () => {
     try {
       const request = await addCustomer(newData);
       await fetchCustomers();
-      setState(StateKeys.CustomerData, [newData, ...(customerData ?? [])]);
+      setState(StateKeys.CustomerData, [request.data, ...(customerData ?? [])]);
       setNotification({ success: request.message, error: null });
       console.log("Customer added successfully");
       setTimeout(() => setNotification({ success: null, error: null }), 5000);
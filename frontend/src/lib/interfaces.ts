@@ -32,6 +32,7 @@ export interface Article extends FieldValues {
   manufacturer: string;
   category: string;
   quantity?: number;
+  deleted_at?: string | null;
 }
 
 export interface ArticleSelection {
@@ -46,6 +47,7 @@ export interface Customer extends FieldValues {
   location: string;
   zip_code: number;
   email: string;
+  deleted_at?: string | null;
 }
 
 export interface Order extends FieldValues {
@@ -55,11 +57,49 @@ export interface Order extends FieldValues {
   date: string;
   order_type: OrderType;
   status: DeliveryStatus;
+  deleted_at?: string | null;
 }
 
 export interface OrderItem {
   article: Article;
   quantity: number;
+  unit_price?: number;
+  article_name?: string;
+}
+
+export interface Page<T> {
+  items: T[];
+  total: number;
+  limit: number | null;
+  offset: number;
+}
+
+export interface ImportReport {
+  dry_run: boolean;
+  committed: boolean;
+  total: number;
+  ok: number;
+  inserted: number;
+  updated: number;
+  skipped: number;
+  duplicate: number;
+  invalid: number;
+  rows: {
+    line: number;
+    status: "ok" | "skipped" | "duplicate" | "invalid";
+    action: "inserted" | "updated" | null;
+    reason: string | null;
+  }[];
+}
+
+export interface Job {
+  job_id: number;
+  kind: string;
+  status: "queued" | "running" | "completed" | "failed" | "cancelled";
+  total: number;
+  processed: number;
+  error: string | null;
+  report: ImportReport | null;
 }
 
 export interface ArticleStatistics {
@@ -88,6 +128,7 @@ export enum DeliveryStatus {
   Completed = "Completed",
   Shipped = "Shipped",
   Delivered = "Delivered",
+  Cancelled = "Cancelled",
 }
 
 export enum OrderType {
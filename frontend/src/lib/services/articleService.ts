@@ -1,11 +1,12 @@
 // articleService.ts
 import { del, post, get, put } from "@/lib/api";
-import { Article } from "@/lib/interfaces";
+import { Article, Page } from "@/lib/interfaces";
 import { handleApiError } from "../error";
 
 export const fetchArticles = async (): Promise<Article[]> => {
   try {
-    return await get({ route: "/articles" });
+    const page: Page<Article> = await get({ route: "/articles" });
+    return page.items;
   } catch (error) {
     handleApiError(error, "Error while fetching articles:");
     throw error;
@@ -1,11 +1,12 @@
 //customerServices.ts
 import { del, post, get } from "@/lib/api";
-import { Customer } from "@/lib/interfaces";
+import { Customer, Page } from "@/lib/interfaces";
 import { handleApiError } from "@/lib/error";
 
 export const fetchCustomers = async (): Promise<Customer[]> => {
   try {
-    return await get({ route: "/customers" });
+    const page: Page<Customer> = await get({ route: "/customers" });
+    return page.items;
   } catch (error) {
     handleApiError(error, "Error while fetching customers:");
     throw error;
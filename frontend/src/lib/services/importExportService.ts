@@ -1,27 +1,44 @@
 // importExportService.ts
-import { post } from "@/lib/api";
-import { Order } from "@/lib/interfaces";
+import { get, post } from "@/lib/api";
+import { Job, Order } from "@/lib/interfaces";
 import { handleApiError } from "../error";
 
-const importCSV = async (file: File, route: string): Promise<void> => {
+const JOB_POLL_INTERVAL = 500;
+
+// Imports run as background jobs, polls the job until it is done
+const waitForJob = async (job: Job): Promise<Job> => {
+  while (job.status === "queued" || job.status === "running") {
+    await new Promise((resolve) => setTimeout(resolve, JOB_POLL_INTERVAL));
+    job = await get({ route: `/jobs/${job.job_id}` });
+  }
+  return job;
+};
+
+// Excel workbooks are imported from the sheet of the entity, other files as CSV
+const importFile = async (file: File, entity: string): Promise<void> => {
   const formData = new FormData();
   formData.append("file", file);
+  const format = file.name.toLowerCase().endsWith(".xlsx") ? "xlsx" : "csv";
+  const route = `/${entity}/import_${format}`;
 
   try {
-    await post({ route, body: formData });
+    const job = await waitForJob(await post({ route, body: formData }));
+    if (job.status === "failed") {
+      console.error("Error while importing file:", job.error);
+    }
   } catch (error) {
-    handleApiError(error, "Error while importing csv file:");
+    handleApiError(error, "Error while importing file:");
   }
 };
 
 export const importArticleCSV = (file: File): Promise<void> =>
-  importCSV(file, "/articles/import_csv");
+  importFile(file, "articles");
 
 export const importCustomerCSV = (file: File): Promise<void> =>
-  importCSV(file, "/customers/import_csv");
+  importFile(file, "customers");
 
 export const importOrderCSV = (file: File): Promise<void> =>
-  importCSV(file, "/orders/import_csv");
+  importFile(file, "orders");
 
 export const pdf_gen = async (order: Order): Promise<void> => {
   try {
@@ -1,11 +1,12 @@
 // orderServices.ts
 import { del, post, get } from "@/lib/api";
-import { Order } from "@/lib/interfaces";
+import { Order, Page } from "@/lib/interfaces";
 import { handleApiError } from "../error";
 
 export const fetchOrders = async (): Promise<Order[]> => {
   try {
-    return await get({ route: "/orders" });
+    const page: Page<Order> = await get({ route: "/orders" });
+    return page.items;
   } catch (error) {
     handleApiError(error, "Error while fetching orders:");
     throw error;
@@ -24,9 +24,9 @@ export const AddOrder = () => {
     {
       label: "Order ID",
       name: "order_id",
-      placeholder: "Order ID",
+      placeholder: "Order ID (optional)",
       valueAsNumber: true,
-      required: true,
+      required: false,
     },
     {
       label: "Customer ID",
@@ -86,7 +86,7 @@ export const AddOrder = () => {
       try {
         const request = await addOrder(newData);
         await fetchOrders();
-        setState(StateKeys.OrderData, [newData, ...(orderData ?? [])]);
+        setState(StateKeys.OrderData, [request.data, ...(orderData ?? [])]);
         setSelectedArticle(null);
         setNotification({ success: request.message, error: null });
         console.log("Order added successfully");
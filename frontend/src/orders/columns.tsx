@@ -147,6 +147,8 @@ export const columns: ColumnDef<Order>[] = [
           return <Badge className="bg-blue-700">Shipped</Badge>;
         case DeliveryStatus.Delivered:
           return <Badge className="bg-teal-700">Delivered</Badge>;
+        case DeliveryStatus.Cancelled:
+          return <Badge className="bg-red-700">Cancelled</Badge>;
       }
     },
   },
@@ -16,7 +16,7 @@ export const columns: ColumnDef<OrderItem>[] = [
     header: "Article",
     cell: ({ row }) => {
       const article: Article = row.getValue("article");
-      return article.name;
+      return row.original.article_name ?? article.name;
     },
   },
   {
@@ -28,7 +28,7 @@ export const columns: ColumnDef<OrderItem>[] = [
     header: "Price",
     cell: ({ row }) => {
       const article: Article = row.getValue("article");
-      const price = article.price;
+      const price = row.original.unit_price ?? article.price;
       return <div>{price.toFixed(2)} €</div>;
     },
   },
@@ -38,7 +38,7 @@ export const columns: ColumnDef<OrderItem>[] = [
     cell: ({ row }) => {
       const article: Article = row.getValue("article");
       const quantity: number = row.getValue("quantity");
-      const price: number = article.price;
+      const price: number = row.original.unit_price ?? article.price;
 
       const totalPrice = price * quantity;
 